@@ -0,0 +1,180 @@
+use crate::{faucet::FaucetConfig, mint_error::MintError};
+
+/// Amount (in whole tokens) minted when the request does not specify one
+pub const DEFAULT_MINT_AMOUNT: &str = "100";
+/// Largest amount (in whole tokens) a single request can mint
+pub const DEFAULT_MAX_MINT_AMOUNT: &str = "1000";
+
+/// Mint amount limits of a faucet in base units
+#[derive(Debug, Clone, Copy)]
+pub struct MintAmountConfig {
+    pub decimals: u8,
+    pub default_amount: u64,
+    pub max_amount: u64,
+}
+
+impl MintAmountConfig {
+    /// Limits for `faucet`, its own `mint_default_amount` and `mint_max_amount` take precedence
+    /// over `MINT_DEFAULT_AMOUNT` and `MINT_MAX_AMOUNT`, amounts are given in whole tokens
+    pub fn for_faucet(faucet: &FaucetConfig) -> Result<Self, String> {
+        let decimals = faucet.decimals;
+        let default_amount = faucet.mint_default_amount.clone().unwrap_or_else(|| {
+            std::env::var("MINT_DEFAULT_AMOUNT").unwrap_or_else(|_| DEFAULT_MINT_AMOUNT.to_string())
+        });
+        let max_amount = faucet.mint_max_amount.clone().unwrap_or_else(|| {
+            std::env::var("MINT_MAX_AMOUNT").unwrap_or_else(|_| DEFAULT_MAX_MINT_AMOUNT.to_string())
+        });
+        let config = Self {
+            decimals,
+            default_amount: parse_token_amount(&default_amount, decimals)
+                .map_err(|err| format!("Invalid default amount for {}: {}", faucet.symbol, err))?,
+            max_amount: parse_token_amount(&max_amount, decimals)
+                .map_err(|err| format!("Invalid max amount for {}: {}", faucet.symbol, err))?,
+        };
+        if config.default_amount > config.max_amount {
+            return Err(format!(
+                "Default amount of {} is larger than its max amount",
+                faucet.symbol
+            ));
+        }
+        Ok(config)
+    }
+
+    /// Resolves the requested amount (in whole tokens) to base units, falling back to the default
+    /// amount when none is given
+    pub fn resolve(&self, amount: Option<&str>) -> Result<u64, MintError> {
+        let amount = match amount {
+            Some(amount) => {
+                parse_token_amount(amount, self.decimals).map_err(MintError::InvalidAmount)?
+            }
+            None => return Ok(self.default_amount),
+        };
+        if amount == 0 {
+            return Err(MintError::AmountOutOfRange(
+                "Amount must be greater than 0".to_string(),
+            ));
+        }
+        if amount > self.max_amount {
+            return Err(MintError::AmountOutOfRange(format!(
+                "Amount exceeds the maximum of {} per request",
+                format_token_amount(self.max_amount, self.decimals)
+            )));
+        }
+        Ok(amount)
+    }
+}
+
+/// Converts an amount in whole tokens (e.g. `10.5`) to base units without going through floats
+pub fn parse_token_amount(amount: &str, decimals: u8) -> Result<u64, String> {
+    let amount = amount.trim();
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(format!("Invalid amount format: {}", amount));
+    }
+    if !whole
+        .chars()
+        .chain(fraction.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return Err(format!("Invalid amount format: {}", amount));
+    }
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > decimals as usize {
+        return Err(format!(
+            "Amount {} has more than {} decimal places",
+            amount, decimals
+        ));
+    }
+
+    let overflow = || format!("Amount {} is too large", amount);
+    let scale = 10u64.checked_pow(decimals as u32).ok_or_else(overflow)?;
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| overflow())?
+    };
+    let fraction: u64 = if fraction.is_empty() {
+        0
+    } else {
+        let padding = 10u64.pow((decimals as usize - fraction.len()) as u32);
+        fraction.parse::<u64>().map_err(|_| overflow())? * padding
+    };
+    whole
+        .checked_mul(scale)
+        .and_then(|base| base.checked_add(fraction))
+        .ok_or_else(overflow)
+}
+
+/// Formats base units as whole tokens, inverse of [`parse_token_amount`]
+pub fn format_token_amount(amount: u64, decimals: u8) -> String {
+    let scale = 10u64.pow(decimals as u32);
+    let fraction = amount % scale;
+    if fraction == 0 {
+        return (amount / scale).to_string();
+    }
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    format!("{}.{}", amount / scale, fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_whole_and_fractional_amounts() {
+        assert_eq!(parse_token_amount("10", 8), Ok(1_000_000_000));
+        assert_eq!(parse_token_amount("10.5", 8), Ok(1_050_000_000));
+        assert_eq!(parse_token_amount(".5", 8), Ok(50_000_000));
+        assert_eq!(parse_token_amount("5.", 8), Ok(500_000_000));
+        assert_eq!(parse_token_amount(" 7 ", 0), Ok(7));
+    }
+
+    #[test]
+    fn ignores_trailing_zeros_past_the_decimals() {
+        assert_eq!(parse_token_amount("1.2300", 2), Ok(123));
+        assert_eq!(parse_token_amount("3.000000000", 0), Ok(3));
+    }
+
+    #[test]
+    fn rejects_too_many_decimals() {
+        assert!(parse_token_amount("1.234", 2).is_err());
+        assert!(parse_token_amount("0.1", 0).is_err());
+    }
+
+    #[test]
+    fn rejects_amounts_above_u64() {
+        assert_eq!(parse_token_amount("184467440737.09551615", 8), Ok(u64::MAX));
+        assert!(parse_token_amount("184467440737.09551616", 8).is_err());
+        assert!(parse_token_amount("18446744073709551616", 0).is_err());
+        assert!(parse_token_amount("1", 20).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_amounts() {
+        for amount in ["", ".", " ", "1.2.3", "-1", "+1", "1e5", "1,5", "abc"] {
+            assert!(
+                parse_token_amount(amount, 8).is_err(),
+                "{:?} should not parse",
+                amount
+            );
+        }
+    }
+
+    #[test]
+    fn formats_base_units_as_tokens() {
+        assert_eq!(format_token_amount(1_050_000_000, 8), "10.5");
+        assert_eq!(format_token_amount(1_000_000_000, 8), "10");
+        assert_eq!(format_token_amount(5, 2), "0.05");
+        assert_eq!(format_token_amount(0, 8), "0");
+        assert_eq!(format_token_amount(42, 0), "42");
+        assert_eq!(format_token_amount(u64::MAX, 8), "184467440737.09551615");
+    }
+
+    #[test]
+    fn format_is_the_inverse_of_parse() {
+        for amount in [0, 1, 50_000_000, 1_050_000_000, 123_456_789, u64::MAX] {
+            let formatted = format_token_amount(amount, 8);
+            assert_eq!(parse_token_amount(&formatted, 8), Ok(amount));
+        }
+    }
+}
@@ -1,245 +1,1172 @@
-use std::collections::VecDeque;
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use miden_client::Felt;
-use miden_client::account::AccountId;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{ConnectInfo, Path, Query};
+use axum::http::{HeaderMap, HeaderName, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::{Stream, StreamExt, stream};
+use miden_client::account::{AccountId, NetworkId};
 use miden_client::address::{Address, AddressId};
 use miden_client::asset::FungibleAsset;
-use miden_client::note::{NoteType, create_p2id_note};
-use miden_client::rpc::Endpoint;
+use miden_client::note::{Note, NoteId, NoteType, create_p2id_note, create_p2ide_note};
+use miden_client::rpc::{GrpcClient, NodeRpcClient};
+use miden_client::store::TransactionFilter;
 use miden_client::transaction::{OutputNote, TransactionRequestBuilder};
-use miden_faucet_server::utils::init_client_with_custom_sync_state;
-use threadpool::ThreadPool;
+use miden_client::transaction::{TransactionId, TransactionStatus};
+use miden_client::{Client, Felt, Word};
+use miden_faucet_server::amount::MintAmountConfig;
+use miden_faucet_server::faucet::{FaucetConfig, configured_faucets};
+use miden_faucet_server::keystore::{FaucetKeyStore, KEYSTORE_DIR};
+use miden_faucet_server::mint_error::MintError;
+use miden_faucet_server::mint_queue::{
+    MintBatchConfig, MintNoteTypeConfig, MintRequestRecord, STATUS_BATCHING, STATUS_COMMITTED,
+    STATUS_PROVING, STATUS_QUEUED, STATUS_SUBMITTED, claim_queued, count_queued,
+    create_mint_requests_table, enqueue_mint_request, get_mint_request, get_proving_requests,
+    get_queue_position, get_request_ids_for_tx, get_submitted_tx_ids, get_undelivered_requests,
+    mark_delivered, mark_delivery_failed, mark_failed, mark_proving, mark_submitted,
+    mark_tx_committed, mark_tx_failed, mark_tx_submitted, requeue_interrupted, requeue_requests,
+    requeue_tx,
+};
+use miden_faucet_server::network::NetworkConfig;
+use miden_faucet_server::pow::{PowChallenge, PowGate};
+use miden_faucet_server::prover::TxProver;
+use miden_faucet_server::rate_limit::{
+    check_rate_limit, create_rate_limit_table, mint_cooldown_secs, record_rate_limit,
+    release_failed_rate_limits, retry_after_secs,
+};
+use miden_faucet_server::reclaim::{ReclaimConfig, reclaim_expired_notes};
+use miden_faucet_server::server::cors_layer;
+use miden_faucet_server::utils::{FaucetClient, MINT_DB, now_secs};
+use rusqlite::{Connection, TransactionBehavior};
+use serde::{Deserialize, Serialize};
 use tokio::runtime::Builder;
-use tokio::sync::oneshot;
+use tokio::sync::{Notify, broadcast};
+use tower::ServiceBuilder;
 
 use lazy_static::lazy_static;
 
 lazy_static! {
-    pub static ref FAUCET_ID: AccountId =
-        AccountId::from_hex(&std::env::var("FAUCET_ID").unwrap()).unwrap();
+    /// faucets this server mints from, the first one serves `POST /mint`
+    static ref FAUCETS: Vec<ServedFaucet> = configured_faucets()
+        .and_then(|faucets| faucets.into_iter().map(ServedFaucet::new).collect())
+        .expect("Invalid faucet configuration");
     pub static ref CLIENT_DB: String = std::env::var("CLIENT_DB").unwrap();
+    /// unlocked once at startup, shared by every client the queue processor builds
+    static ref KEYSTORE: Arc<FaucetKeyStore> =
+        Arc::new(FaucetKeyStore::unlock(KEYSTORE_DIR).expect("Failed to unlock keystore"));
+    static ref NETWORK: NetworkConfig =
+        NetworkConfig::from_env().expect("Invalid network configuration");
+    static ref POW_GATE: PowGate = PowGate::from_env();
+    static ref MINT_BATCH_CONFIG: MintBatchConfig = MintBatchConfig::from_env();
+    static ref RECLAIM_CONFIG: ReclaimConfig = ReclaimConfig::from_env();
+    static ref MINT_NOTE_TYPE_CONFIG: MintNoteTypeConfig =
+        MintNoteTypeConfig::from_env(NETWORK.note_transport_url.is_some())
+            .expect("Invalid mint note type configuration");
+    /// wakes the queue processor before the flush interval when a full batch is waiting
+    static ref MINT_FLUSH: Notify = Notify::new();
 }
 
-#[derive(Debug)]
-struct MintRequest {
-    address: String,
+/// A faucet served by this server along with its mint limits
+struct ServedFaucet {
+    config: FaucetConfig,
+    id: AccountId,
+    amounts: MintAmountConfig,
+    cooldown_secs: u64,
+}
+
+impl ServedFaucet {
+    fn new(config: FaucetConfig) -> Result<Self, String> {
+        Ok(Self {
+            id: config.account_id()?,
+            amounts: MintAmountConfig::for_faucet(&config)?,
+            cooldown_secs: config.mint_cooldown_secs.unwrap_or_else(mint_cooldown_secs),
+            config,
+        })
+    }
+
+    /// Looks a faucet up by hex id or symbol
+    fn find(key: &str) -> Result<&'static Self, MintError> {
+        FAUCETS
+            .iter()
+            .find(|faucet| faucet.config.matches(key))
+            .ok_or_else(|| MintError::NotFound(format!("Unknown faucet {}", key)))
+    }
+}
+
+/// Outcome of a single mint request within a batch
+#[derive(Serialize, Debug, Clone)]
+struct MintResult {
+    tx_id: String,
+    note_id: String,
+    account_id: String,
     amount: u64,
-    response_tx: oneshot::Sender<Result<String, String>>,
+    prover: &'static str,
+    /// set when the transaction went through but its private note could not be delivered
+    #[serde(skip)]
+    delivery_error: Option<MintError>,
+}
+
+const STAGE_QUEUED: &str = "queued";
+const STAGE_INCLUDED_IN_BATCH: &str = "included_in_batch";
+const STAGE_PROVING: &str = "proving";
+const STAGE_SUBMITTED: &str = "submitted";
+const STAGE_NOTE_DELIVERED: &str = "note_delivered";
+const STAGE_COMMITTED: &str = "committed";
+const STAGE_FAILED: &str = "failed";
+
+/// How long the transaction of requests left in `proving` by an interrupted run gets to show up
+/// on chain before the requests are minted again
+const INTERRUPTED_TX_GRACE_SECS: u64 = 10 * 60;
+
+/// Progress of a single mint request, streamed to `/mint/{request_id}/events` subscribers
+#[derive(Serialize, Debug, Clone)]
+struct MintEvent {
+    request_id: String,
+    stage: &'static str,
+    tx_id: Option<String>,
+    note_id: Option<String>,
+    /// prover that proved the mint transaction, set once it is submitted
+    prover: Option<String>,
+    error: Option<String>,
+    error_code: Option<String>,
 }
 
-type MintQueue = Arc<Mutex<VecDeque<MintRequest>>>;
+impl MintEvent {
+    fn new(request_id: &str, stage: &'static str) -> Self {
+        Self {
+            request_id: request_id.to_string(),
+            stage,
+            tx_id: None,
+            note_id: None,
+            prover: None,
+            error: None,
+            error_code: None,
+        }
+    }
+
+    fn with_tx(mut self, tx_id: &str, note_id: &str) -> Self {
+        self.tx_id = Some(tx_id.to_string());
+        self.note_id = Some(note_id.to_string());
+        self
+    }
+
+    fn with_prover(mut self, prover: &str) -> Self {
+        self.prover = Some(prover.to_string());
+        self
+    }
+
+    fn with_error(mut self, error: &MintError) -> Self {
+        self.error = Some(error.to_string());
+        self.error_code = Some(error.code().to_string());
+        self
+    }
+
+    fn is_final(&self) -> bool {
+        self.stage == STAGE_COMMITTED || self.stage == STAGE_FAILED
+    }
+
+    fn publish(self) {
+        // no receivers just means nobody is listening
+        let _ = MINT_EVENTS.send(self);
+    }
+
+    /// Event describing the journaled state of a request, sent first to every new subscriber
+    fn from_record(record: &MintRequestRecord) -> Self {
+        let stage = match record.status.as_str() {
+            STATUS_QUEUED => STAGE_QUEUED,
+            STATUS_BATCHING => STAGE_INCLUDED_IN_BATCH,
+            STATUS_PROVING => STAGE_PROVING,
+            STATUS_SUBMITTED => STAGE_SUBMITTED,
+            STATUS_COMMITTED => STAGE_COMMITTED,
+            _ => STAGE_FAILED,
+        };
+        Self {
+            request_id: record.request_id.clone(),
+            stage,
+            tx_id: record.tx_id.clone(),
+            note_id: record.note_id.clone(),
+            prover: record.prover.clone(),
+            error: record.error.clone(),
+            error_code: record.error_code.clone(),
+        }
+    }
+
+    fn into_sse(self) -> Event {
+        Event::default()
+            .event(self.stage)
+            .json_data(&self)
+            .expect("mint event is serializable")
+    }
+}
 
 lazy_static! {
-    static ref MINT_QUEUE: MintQueue = Arc::new(Mutex::new(VecDeque::new()));
+    static ref MINT_EVENTS: broadcast::Sender<MintEvent> = broadcast::channel(1024).0;
 }
 
-async fn bulk_mint(requests: &[(String, u64)]) -> Result<String, String> {
-    let mut client =
-        init_client_with_custom_sync_state(&CLIENT_DB, Endpoint::testnet(), *FAUCET_ID).await;
+fn decode_target(address: &str) -> Result<(NetworkId, AccountId, Address), MintError> {
+    let (network_id, addr) = Address::decode(address).map_err(|err| {
+        MintError::InvalidAddress(format!("Invalid address {}: {}", address, err))
+    })?;
+    if network_id != NETWORK.network_id {
+        return Err(MintError::InvalidAddress(format!(
+            "Address {} is not a {} address, expected the {} prefix",
+            address, NETWORK.name, NETWORK.network_id
+        )));
+    }
+    match addr.id() {
+        AddressId::AccountId(id) => Ok((network_id, id, addr)),
+        _ => Err(MintError::UnsupportedAddressKind(format!(
+            "Unsupported address kind: {}, only account addresses can receive tokens",
+            address
+        ))),
+    }
+}
+
+/// Mints all the requests in a single transaction, returns one result per request in the same order
+async fn bulk_mint(
+    client: &mut Client<FaucetKeyStore>,
+    prover: &TxProver,
+    conn: &Connection,
+    faucet_id: AccountId,
+    requests: &[MintRequestRecord],
+) -> Vec<Result<MintResult, MintError>> {
+    let mut results: Vec<Result<MintResult, MintError>> =
+        vec![Err(MintError::Internal("Request was not processed".to_string())); requests.len()];
+    let reclaim_height = match RECLAIM_CONFIG.reclaim_after_blocks {
+        Some(_) => match client.get_sync_height().await {
+            Ok(sync_height) => RECLAIM_CONFIG.reclaim_height(sync_height),
+            Err(err) => {
+                let err = MintError::Internal(format!("Failed to get sync height: {}", err));
+                return vec![Err(err); requests.len()];
+            }
+        },
+        None => None,
+    };
+    // (index in requests, note, recipient account, target address)
     let mut p2id_notes = Vec::new();
-    println!("{:?}", requests);
-    for (address, amount) in requests {
-        let fungible_asset = FungibleAsset::new(*FAUCET_ID, *amount).unwrap();
-        let target = match Address::decode(address) {
-            Ok((_, addr)) => match addr.id() {
-                AddressId::AccountId(id) => id,
-                _ => todo!("Cover More the code has updated"),
-            },
-            Err(_) => continue,
+    for (i, request) in requests.iter().enumerate() {
+        let (address, amount) = (&request.address, &request.amount);
+        let (network_id, target, target_address) = match decode_target(address) {
+            Ok(target) => target,
+            Err(err) => {
+                results[i] = Err(err);
+                continue;
+            }
         };
-        let p2id_note = create_p2id_note(
-            *FAUCET_ID,
-            target,
-            vec![fungible_asset.into()],
-            NoteType::Private,
-            Felt::new(0),
-            client.rng(),
-        )
-        .map_err(|e| e.to_string())?;
-        p2id_notes.push(p2id_note);
+        let note_type = match request.note_type.parse::<NoteType>() {
+            Ok(note_type) => note_type,
+            Err(err) => {
+                results[i] = Err(MintError::InvalidRequest(err.to_string()));
+                continue;
+            }
+        };
+        let fungible_asset = match FungibleAsset::new(faucet_id, *amount) {
+            Ok(asset) => asset,
+            Err(err) => {
+                results[i] = Err(MintError::AmountOutOfRange(format!(
+                    "Invalid amount {}: {}",
+                    amount, err
+                )));
+                continue;
+            }
+        };
+        let note = match reclaim_height {
+            Some(reclaim_height) => create_p2ide_note(
+                faucet_id,
+                target,
+                vec![fungible_asset.into()],
+                Some(reclaim_height),
+                None,
+                note_type,
+                Felt::new(0),
+                client.rng(),
+            ),
+            None => create_p2id_note(
+                faucet_id,
+                target,
+                vec![fungible_asset.into()],
+                note_type,
+                Felt::new(0),
+                client.rng(),
+            ),
+        };
+        match note {
+            Ok(note) => p2id_notes.push((i, note, target.to_bech32(network_id), target_address)),
+            Err(err) => {
+                results[i] = Err(MintError::Internal(format!(
+                    "Failed to create note: {}",
+                    err
+                )))
+            }
+        }
     }
+    if p2id_notes.is_empty() {
+        return results;
+    }
+
+    let (tx_id, prover) =
+        match submit_mint_transaction(client, prover, conn, faucet_id, requests, &p2id_notes).await
+        {
+            Ok(submitted) => submitted,
+            Err(err) => {
+                for (i, _, _, _) in p2id_notes {
+                    results[i] = Err(err.clone());
+                }
+                return results;
+            }
+        };
+    for (i, note, account_id, target_address) in p2id_notes {
+        let note_id = note.id().to_hex();
+        let result = MintResult {
+            tx_id: tx_id.clone(),
+            note_id: note_id.clone(),
+            account_id,
+            amount: requests[i].amount,
+            prover,
+            delivery_error: None,
+        };
+        // public notes are discoverable on chain, only private ones go through the transport
+        if note.metadata().note_type() == NoteType::Public {
+            results[i] = Ok(result);
+            continue;
+        }
+        // the tokens are minted either way, an undelivered note is sent again later
+        let delivery_error = deliver_note(client, note, &target_address).await.err();
+        if delivery_error.is_none() {
+            MintEvent::new(&requests[i].request_id, STAGE_NOTE_DELIVERED)
+                .with_tx(&tx_id, &note_id)
+                .publish();
+        }
+        results[i] = Ok(MintResult {
+            delivery_error,
+            ..result
+        });
+    }
+    results
+}
+
+/// Sends a private note to its recipient through the note transport
+async fn deliver_note(
+    client: &mut Client<FaucetKeyStore>,
+    note: Note,
+    target_address: &Address,
+) -> Result<(), MintError> {
+    let note_id = note.id().to_hex();
+    client
+        .send_private_note(note, target_address)
+        .await
+        .map_err(|err| MintError::Transport(format!("Note {} was not delivered: {}", note_id, err)))
+}
+
+/// Sends again the private notes that could not be delivered when they were minted, taking them
+/// from the output notes in the client store. Returns whether the note transport failed again.
+async fn redeliver_notes(
+    client: &mut Client<FaucetKeyStore>,
+    conn: &Connection,
+) -> Result<bool, String> {
+    for request in get_undelivered_requests(conn)? {
+        let (Some(tx_id), Some(note_id)) = (&request.tx_id, &request.note_id) else {
+            continue;
+        };
+        let note = match undelivered_note(client, note_id).await {
+            Ok(note) => note,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        let (_, _, target_address) =
+            decode_target(&request.address).map_err(|err| err.to_string())?;
+        if let Err(err) = deliver_note(client, note, &target_address).await {
+            mark_delivery_failed(conn, &request.request_id, &err)?;
+            return Ok(true);
+        }
+        println!("Delivered note {} on retry", note_id);
+        mark_delivered(conn, &request.request_id)?;
+        MintEvent::new(&request.request_id, STAGE_NOTE_DELIVERED)
+            .with_tx(tx_id, note_id)
+            .publish();
+    }
+    Ok(false)
+}
+
+/// Full note `note_id` as the client stored it when the mint transaction was applied
+async fn undelivered_note(client: &Client<FaucetKeyStore>, note_id: &str) -> Result<Note, String> {
+    let id = NoteId::try_from_hex(note_id)
+        .map_err(|err| format!("Invalid note id {} {}", note_id, err))?;
+    let record = client
+        .get_output_note(id)
+        .await
+        .map_err(|err| format!("Failed to get output note {} {}", note_id, err))?
+        .ok_or_else(|| format!("Output note {} is not in the client store", note_id))?;
+    Note::try_from(record).map_err(|err| format!("Output note {} has no details {}", note_id, err))
+}
+
+/// Executes, proves and submits the mint transaction, publishing progress for every request in it.
+///
+/// The transaction is journaled once executed, so a run interrupted while proving or submitting
+/// it can find out whether it reached the node instead of minting the requests again.
+async fn submit_mint_transaction(
+    client: &mut Client<FaucetKeyStore>,
+    prover: &TxProver,
+    conn: &Connection,
+    faucet_id: AccountId,
+    requests: &[MintRequestRecord],
+    p2id_notes: &[(usize, Note, String, Address)],
+) -> Result<(String, &'static str), MintError> {
     let output_notes: Vec<OutputNote> = p2id_notes
-        .clone()
-        .into_iter()
-        .map(OutputNote::Full)
+        .iter()
+        .map(|(_, note, _, _)| OutputNote::Full(note.clone()))
         .collect();
     let transaction_request = TransactionRequestBuilder::new()
         .own_output_notes(output_notes)
         .build()
-        .unwrap();
-    let digest = client
-        .submit_new_transaction(*FAUCET_ID, transaction_request)
+        .map_err(|err| MintError::Internal(format!("Failed to build transaction: {}", err)))?;
+
+    let tx_result = client
+        .execute_transaction(faucet_id, transaction_request)
         .await
-        .unwrap();
-    for (i, note) in p2id_notes.into_iter().enumerate() {
-        client
-            .send_private_note(note, &Address::decode(&requests[i].0).unwrap().1)
-            .await
-            .unwrap();
+        .map_err(|err| MintError::Execution(format!("Failed to execute transaction: {}", err)))?;
+    let tx_id = tx_result.executed_transaction().id().to_hex();
+    for (i, note, account_id, _) in p2id_notes {
+        mark_proving(
+            conn,
+            &requests[*i].request_id,
+            &tx_id,
+            &note.id().to_hex(),
+            &note.nullifier().to_hex(),
+            account_id,
+        )
+        .map_err(MintError::Internal)?;
+    }
+    for (i, note, _, _) in p2id_notes {
+        MintEvent::new(&requests[*i].request_id, STAGE_PROVING)
+            .with_tx(&tx_id, &note.id().to_hex())
+            .publish();
     }
-    Ok(digest.to_hex())
+    let (proven_transaction, prover) = prover.prove(client, &tx_result).await?;
+    println!("Mint transaction {} proven by the {} prover", tx_id, prover);
+    let submission_height = client
+        .submit_proven_transaction(proven_transaction, &tx_result)
+        .await
+        .map_err(|err| {
+            MintError::SubmissionRejected(format!("Failed to submit transaction: {}", err))
+        })?;
+    client
+        .apply_transaction(&tx_result, submission_height)
+        .await
+        .map_err(|err| MintError::Internal(format!("Failed to apply transaction: {}", err)))?;
+    for (i, note, _, _) in p2id_notes {
+        MintEvent::new(&requests[*i].request_id, STAGE_SUBMITTED)
+            .with_tx(&tx_id, &note.id().to_hex())
+            .with_prover(prover)
+            .publish();
+    }
+    Ok((tx_id, prover))
 }
 
-fn start_queue_processor() -> JoinHandle<()> {
-    let queue = MINT_QUEUE.clone();
+/// Marks submitted requests as committed (or failed) once the synced client sees their transaction
+async fn update_committed(
+    client: &Client<FaucetKeyStore>,
+    conn: &Connection,
+) -> Result<(), String> {
+    let tx_ids = get_submitted_tx_ids(conn)?
+        .into_iter()
+        .filter_map(|tx_id| Word::try_from(tx_id.as_str()).ok())
+        .map(TransactionId::from)
+        .collect::<Vec<_>>();
+    if tx_ids.is_empty() {
+        return Ok(());
+    }
+    let records = client
+        .get_transactions(TransactionFilter::Ids(tx_ids))
+        .await
+        .map_err(|err| format!("Failed to get transactions {}", err))?;
+    for record in records {
+        match record.status {
+            TransactionStatus::Committed { block_number, .. } => {
+                println!(
+                    "Mint transaction {} committed at block {}",
+                    record.id.to_hex(),
+                    block_number
+                );
+                let tx_id = record.id.to_hex();
+                for request_id in get_request_ids_for_tx(conn, &tx_id, STATUS_SUBMITTED)? {
+                    MintEvent::new(&request_id, STAGE_COMMITTED).publish();
+                }
+                mark_tx_committed(conn, &tx_id)?
+            }
+            TransactionStatus::Discarded(cause) => {
+                let tx_id = record.id.to_hex();
+                let error =
+                    MintError::SubmissionRejected(format!("Transaction discarded: {:?}", cause));
+                for request_id in get_request_ids_for_tx(conn, &tx_id, STATUS_SUBMITTED)? {
+                    MintEvent::new(&request_id, STAGE_FAILED)
+                        .with_error(&error)
+                        .publish();
+                }
+                mark_tx_failed(conn, &tx_id, &error)?
+            }
+            TransactionStatus::Pending => {}
+        }
+    }
+    Ok(())
+}
 
-    thread::spawn(move || {
-        let rt = Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .expect("Failed to build Tokio runtime");
+/// Settles the requests an interrupted run left in `proving`. If the client applied their
+/// transaction it reached the node and [`update_committed`] follows it from there, if their
+/// notes are on chain it is committed, and once [`INTERRUPTED_TX_GRACE_SECS`] passed without
+/// either the requests go back to the queue.
+async fn resume_interrupted(
+    client: &Client<FaucetKeyStore>,
+    conn: &Connection,
+) -> Result<(), String> {
+    let mut interrupted: BTreeMap<String, Vec<MintRequestRecord>> = BTreeMap::new();
+    for request in get_proving_requests(conn)? {
+        if let Some(tx_id) = request.tx_id.clone() {
+            interrupted.entry(tx_id).or_default().push(request);
+        }
+    }
+    if interrupted.is_empty() {
+        return Ok(());
+    }
+    let rpc = GrpcClient::new(&NETWORK.endpoint, 10_000);
+    let now = now_secs();
+    for (tx_id, requests) in interrupted {
+        let id = Word::try_from(tx_id.as_str())
+            .map(TransactionId::from)
+            .map_err(|err| format!("Invalid transaction id {} {}", tx_id, err))?;
+        let applied = client
+            .get_transactions(TransactionFilter::Ids(vec![id]))
+            .await
+            .map_err(|err| format!("Failed to get transactions {}", err))?;
+        if !applied.is_empty() {
+            mark_tx_submitted(conn, &tx_id)?;
+            continue;
+        }
 
-        loop {
-            thread::sleep(Duration::from_secs(5));
+        let note_ids = requests
+            .iter()
+            .filter_map(|request| request.note_id.as_deref())
+            .filter_map(|note_id| NoteId::try_from_hex(note_id).ok())
+            .collect::<Vec<_>>();
+        let on_chain = rpc
+            .get_notes_by_id(&note_ids)
+            .await
+            .map_err(|err| format!("Failed to get notes of transaction {} {}", tx_id, err))?;
+        if !on_chain.is_empty() {
+            println!("Interrupted mint transaction {} is committed", tx_id);
+            for request in &requests {
+                MintEvent::new(&request.request_id, STAGE_COMMITTED).publish();
+            }
+            mark_tx_submitted(conn, &tx_id)?;
+            mark_tx_committed(conn, &tx_id)?;
+        } else if requests
+            .iter()
+            .all(|request| now.saturating_sub(request.updated_at) >= INTERRUPTED_TX_GRACE_SECS)
+        {
+            println!(
+                "Interrupted mint transaction {} never reached the chain, requeueing its requests",
+                tx_id
+            );
+            for request in &requests {
+                MintEvent::new(&request.request_id, STAGE_QUEUED).publish();
+            }
+            requeue_tx(conn, &tx_id)?;
+        }
+    }
+    Ok(())
+}
 
-            let mut pending_requests = Vec::new();
+/// Mints a claimed batch, which always belongs to a single faucet, and journals the outcome of
+/// every request. Returns whether a note could not be delivered through the note transport.
+async fn process_batch(
+    client: &mut Client<FaucetKeyStore>,
+    prover: &TxProver,
+    conn: &Connection,
+    batch: Vec<MintRequestRecord>,
+) -> Result<bool, String> {
+    let faucet_id = batch[0].faucet_id.clone();
+    println!(
+        "Processing batch of {} requests for faucet {}",
+        batch.len(),
+        faucet_id
+    );
+    for request in &batch {
+        MintEvent::new(&request.request_id, STAGE_INCLUDED_IN_BATCH).publish();
+    }
 
-            {
-                let mut queue_lock = queue.lock().unwrap();
-                while let Some(request) = queue_lock.pop_front() {
-                    pending_requests.push(request);
+    // the faucet may have been removed from the config since the requests were queued
+    let results = match ServedFaucet::find(&faucet_id) {
+        Ok(faucet) => bulk_mint(client, prover, conn, faucet.id, &batch).await,
+        Err(_) => {
+            let err = MintError::NotFound(format!("Faucet {} is no longer served", faucet_id));
+            vec![Err(err); batch.len()]
+        }
+    };
+
+    let mut transport_failed = false;
+    for (request, result) in batch.into_iter().zip(results) {
+        match result {
+            Ok(result) => {
+                mark_submitted(
+                    conn,
+                    &request.request_id,
+                    &result.tx_id,
+                    &result.note_id,
+                    &result.account_id,
+                    result.prover,
+                )?;
+                if let Some(err) = result.delivery_error {
+                    eprintln!("{}", err);
+                    MintEvent::new(&request.request_id, STAGE_SUBMITTED)
+                        .with_tx(&result.tx_id, &result.note_id)
+                        .with_prover(result.prover)
+                        .with_error(&err)
+                        .publish();
+                    mark_delivery_failed(conn, &request.request_id, &err)?;
+                    transport_failed = true;
                 }
             }
-
-            if pending_requests.is_empty() {
-                println!("No pending requests to process");
-                continue;
+            Err(err) => {
+                MintEvent::new(&request.request_id, STAGE_FAILED)
+                    .with_error(&err)
+                    .publish();
+                mark_failed(conn, &request.request_id, &err)?
             }
+        }
+    }
+    Ok(transport_failed)
+}
 
-            println!("Processing batch of {} requests", pending_requests.len());
-
-            let mint_data: Vec<(String, u64)> = pending_requests
-                .iter()
-                .map(|req| (req.address.clone(), req.amount))
-                .collect();
+/// Claims the next batch and tells the subscribers of requests that ran out of attempts
+fn claim_batch(conn: &mut Connection, limit: usize) -> Result<Vec<MintRequestRecord>, String> {
+    let (batch, exhausted) = claim_queued(conn, limit)?;
+    for request_id in exhausted {
+        MintEvent::new(&request_id, STAGE_FAILED)
+            .with_error(&MintError::AttemptsExhausted)
+            .publish();
+    }
+    Ok(batch)
+}
 
-            let result = rt.block_on(bulk_mint(&mint_data));
+/// Puts a claimed batch back in the queue when it could not be minted for reasons unrelated to
+/// the requests themselves
+fn requeue_batch(conn: &Connection, batch: &[MintRequestRecord]) {
+    let request_ids: Vec<&str> = batch
+        .iter()
+        .map(|request| request.request_id.as_str())
+        .collect();
+    if let Err(err) = requeue_requests(conn, &request_ids) {
+        eprintln!("{}", err);
+    }
+}
 
-            for request in pending_requests {
-                let _ = request.response_tx.send(result.clone());
+/// Runs the queue processor on its own thread and restarts it if it ever panics, so the server
+/// never keeps accepting requests that nothing will mint
+fn start_queue_processor(config: MintBatchConfig) -> JoinHandle<()> {
+    thread::spawn(move || {
+        loop {
+            let processor = thread::spawn(move || run_queue_processor(config));
+            if processor.join().is_ok() {
+                return;
+            }
+            eprintln!("Queue processor crashed, restarting");
+            // whatever batch it was working on goes back to the queue, counting the attempt
+            match Connection::open(MINT_DB)
+                .map_err(|err| err.to_string())
+                .and_then(|conn| requeue_interrupted(&conn))
+            {
+                Ok(requeued) => eprintln!("Requeued {} interrupted mint requests", requeued),
+                Err(err) => eprintln!("{}", err),
             }
+            thread::sleep(Duration::from_secs(1));
         }
     })
 }
 
-fn handle_client(mut stream: TcpStream) {
-    let mut buffer = [0; 1024];
-
-    // Read incoming request
-    match stream.read(&mut buffer) {
-        Ok(_) => {
-            // Print the request
-            let request = String::from_utf8_lossy(&buffer);
-            let request_path = request.lines().next().unwrap_or("");
-            let rt = Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .expect("Failed to build Tokio runtime");
-
-            if request.starts_with("GET /mint/") {
-                let params = request_path.split(" ").nth(1).unwrap_or("");
-                let parts: Vec<&str> = params.trim_start_matches("/mint/").split('/').collect();
-                if parts.len() == 2 {
-                    let address = parts[0];
-                    let amount = parts[1];
-
-                    let amount: u64 = match amount.parse() {
-                        Ok(a) => a,
-                        Err(_) => {
-                            let response = "HTTP/1.1 400 BAD REQUEST\r\nContent-Type: text/plain\r\n\r\nInvalid amount format.";
-                            stream.write_all(response.as_bytes()).unwrap();
-                            return;
-                        }
-                    };
+fn run_queue_processor(config: MintBatchConfig) {
+    let rt = Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build Tokio runtime");
+    let mut conn = Connection::open(MINT_DB).expect("Cannot open mint db");
+    // built lazily and dropped whenever it fails to sync or to deliver a note, so a dead rpc or
+    // note transport connection is replaced on the next batch
+    let mut faucet_client: Option<FaucetClient> = None;
+    let mut last_reclaim = Instant::now();
 
-                    let (tx, rx) = oneshot::channel();
+    loop {
+        // wait for the flush interval, or less if a full batch is already queued
+        // the timer has to be created inside the runtime
+        let _ = rt.block_on(async {
+            tokio::time::timeout(config.flush_interval, MINT_FLUSH.notified()).await
+        });
 
-                    let mint_request = MintRequest {
-                        address: address.to_string(),
-                        amount,
-                        response_tx: tx,
-                    };
+        // requests that failed since the last round no longer hold their callers back
+        if let Err(err) = release_failed_rate_limits(&conn) {
+            eprintln!("{}", err);
+        }
 
-                    {
-                        let mut queue = MINT_QUEUE.lock().unwrap();
-                        queue.push_back(mint_request);
-                    }
+        // submitted transactions are followed until they commit, interrupted ones until they
+        // are settled and undelivered notes until they are delivered
+        let has_pending = match get_submitted_tx_ids(&conn).and_then(|tx_ids| {
+            Ok(!tx_ids.is_empty()
+                || !get_proving_requests(&conn)?.is_empty()
+                || !get_undelivered_requests(&conn)?.is_empty())
+        }) {
+            Ok(has_pending) => has_pending,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        let batch = match claim_batch(&mut conn, config.batch_size) {
+            Ok(batch) => batch,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
 
-                    let res = rt.block_on(async {
-                        match tokio::time::timeout(Duration::from_secs(120), rx).await {
-                            Ok(Ok(result)) => result,
-                            Ok(Err(_)) => Err("Channel closed".to_string()),
-                            Err(_) => Err("Timeout waiting for batch processing".to_string()),
-                        }
-                    });
-
-                    let response = match res {
-                        Ok(digest) => format!(
-                            "HTTP/1.1 200 OK\r\n\
-Content-Type: text/plain\r\n\
-Access-Control-Allow-Origin: *\r\n\
-Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
-Access-Control-Allow-Headers: Content-Type\r\n\
-\r\n{}",
-                            digest
+        let reclaim_due = RECLAIM_CONFIG.reclaim_after_blocks.is_some()
+            && last_reclaim.elapsed() >= RECLAIM_CONFIG.interval;
+
+        if batch.is_empty() && !has_pending && !reclaim_due {
+            continue;
+        }
+
+        rt.block_on(async {
+            let faucet = match faucet_client.as_mut() {
+                Some(faucet) => faucet,
+                None => match FaucetClient::new(
+                    &CLIENT_DB,
+                    NETWORK.clone(),
+                    FAUCETS.iter().map(|faucet| faucet.id).collect(),
+                    KEYSTORE.clone(),
+                )
+                .await
+                {
+                    Ok(faucet) => faucet_client.insert(faucet),
+                    Err(err) => {
+                        eprintln!("Failed to build client, retrying later: {}", err);
+                        requeue_batch(&conn, &batch);
+                        return;
+                    }
+                },
+            };
+            if let Err(err) = faucet.sync().await {
+                eprintln!("Failed to sync state, rebuilding client: {}", err);
+                faucet_client = None;
+                requeue_batch(&conn, &batch);
+                return;
+            }
+            let client = &mut faucet.client;
+            let prover = &faucet.prover;
+            if let Err(err) = resume_interrupted(client, &conn).await {
+                eprintln!("{}", err);
+            }
+            if let Err(err) = update_committed(client, &conn).await {
+                eprintln!("{}", err);
+            }
+            let mut transport_failed = match redeliver_notes(client, &conn).await {
+                Ok(failed) => failed,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    false
+                }
+            };
+            if reclaim_due {
+                last_reclaim = Instant::now();
+                for faucet in FAUCETS.iter() {
+                    match reclaim_expired_notes(client, prover, faucet.id, config.batch_size).await
+                    {
+                        Ok(Some(tx_id)) => println!(
+                            "Submitted recall transaction {} for {}",
+                            tx_id, faucet.config.symbol
                         ),
-                        Err(error) => format!(
-                            "HTTP/1.1 500 INTERNAL SERVER ERROR\r\n\
-Content-Type: text/plain\r\n\
-Access-Control-Allow-Origin: *\r\n\
-Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
-Access-Control-Allow-Headers: Content-Type\r\n\
-\r\nError: {}",
-                            error
+                        Ok(None) => {}
+                        Err(err) => eprintln!(
+                            "Failed to recall expired {} notes: {}",
+                            faucet.config.symbol, err
                         ),
-                    };
-                    stream.write_all(response.as_bytes()).unwrap();
-                } else {
-                    let response = "HTTP/1.1 400 BAD REQUEST\r\nContent-Type: text/plain\r\n\r\nInvalid mint request format. Use /mint/<address>/<amount>";
-                    stream.write_all(response.as_bytes()).unwrap();
+                    }
+                }
+            }
+            // anything beyond the batch size goes into follow-up transactions
+            let mut batch = batch;
+            while !batch.is_empty() {
+                match process_batch(client, prover, &conn, batch).await {
+                    Ok(failed) => transport_failed |= failed,
+                    Err(err) => eprintln!("{}", err),
                 }
-            } else {
-                let response =
-                    "HTTP/1.1 404 NOT FOUND\r\nContent-Type: text/plain\r\n\r\nNot Found";
-                stream.write_all(response.as_bytes()).unwrap();
+                batch = match claim_batch(&mut conn, config.batch_size) {
+                    Ok(batch) => batch,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        break;
+                    }
+                };
+            }
+            if transport_failed {
+                eprintln!("Failed to deliver notes, rebuilding client");
+                faucet_client = None;
             }
+        });
+    }
+}
+
+/// Returns the client ip. Behind the local nginx it is the `X-Real-IP` header, which nginx sets
+/// to the address it got the request from; the header is ignored from any other peer since
+/// clients can send it themselves.
+fn client_ip(headers: &HeaderMap, peer: SocketAddr) -> String {
+    if !peer.ip().is_loopback() {
+        return peer.ip().to_string();
+    }
+    headers
+        .get("x-real-ip")
+        .and_then(|value| value.to_str().ok())
+        .map(|ip| ip.trim().to_string())
+        .filter(|ip| !ip.is_empty())
+        .unwrap_or_else(|| peer.ip().to_string())
+}
+
+#[derive(Deserialize)]
+struct PowQuery {
+    account_id: String,
+}
+
+async fn get_pow_challenge(
+    query: Result<Query<PowQuery>, QueryRejection>,
+) -> Result<Json<PowChallenge>, MintError> {
+    let Query(query) = query.map_err(|err| MintError::InvalidRequest(err.body_text()))?;
+    let (_, account_id, _) = decode_target(&query.account_id)?;
+    let queue_len = Connection::open(MINT_DB)
+        .map_err(|err| err.to_string())
+        .and_then(|conn| count_queued(&conn))
+        .map_err(MintError::Internal)?;
+    Ok(Json(POW_GATE.issue(account_id, queue_len)))
+}
+
+/// Amount in whole tokens, accepted both as a JSON string and as a JSON number
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AmountInput {
+    Text(String),
+    Number(serde_json::Number),
+}
+
+impl AmountInput {
+    fn into_string(self) -> String {
+        match self {
+            AmountInput::Text(amount) => amount,
+            AmountInput::Number(amount) => amount.to_string(),
         }
-        Err(e) => {
-            eprintln!("Failed to read from client: {}", e);
+    }
+}
+
+#[derive(Deserialize)]
+struct MintBody {
+    address: String,
+    amount: Option<AmountInput>,
+    /// `public` or `private`, defaults to `MINT_NOTE_TYPE`
+    note_type: Option<String>,
+    challenge: Option<String>,
+    nonce: Option<u64>,
+}
+
+/// State of a mint request as reported to the caller
+#[derive(Serialize)]
+struct MintStatus {
+    #[serde(flatten)]
+    request: MintRequestRecord,
+    /// position in the queue, only set while the request is queued
+    queue_position: Option<usize>,
+    /// whether the mint transaction has been committed on chain
+    committed: bool,
+}
+
+fn mint_status(conn: &Connection, request_id: &str) -> Result<MintStatus, MintError> {
+    let request = get_mint_request(conn, request_id)
+        .map_err(MintError::Internal)?
+        .ok_or_else(|| MintError::NotFound("Mint request not found".to_string()))?;
+    let queue_position = get_queue_position(conn, request_id).map_err(MintError::Internal)?;
+    Ok(MintStatus {
+        committed: request.status == STATUS_COMMITTED,
+        request,
+        queue_position,
+    })
+}
+
+/// Queues a mint from the default faucet
+async fn mint(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    body: Result<Json<MintBody>, JsonRejection>,
+) -> Result<(StatusCode, Json<MintStatus>), MintError> {
+    let Json(body) = body.map_err(|err| MintError::InvalidRequest(err.body_text()))?;
+    enqueue_mint(&FAUCETS[0], &headers, peer, body)
+}
+
+/// Proof of work and note type of `POST /mint/{faucet}/{address}/{amount}`, which takes the
+/// faucet, target and amount from the path
+#[derive(Deserialize)]
+struct MintPathBody {
+    note_type: Option<String>,
+    challenge: Option<String>,
+    nonce: Option<u64>,
+}
+
+/// Queues a mint from the faucet given by hex id or symbol
+async fn mint_from_faucet(
+    Path((faucet, address, amount)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    body: Result<Json<MintPathBody>, JsonRejection>,
+) -> Result<(StatusCode, Json<MintStatus>), MintError> {
+    let faucet = ServedFaucet::find(&faucet)?;
+    let Json(body) = body.map_err(|err| MintError::InvalidRequest(err.body_text()))?;
+    let body = MintBody {
+        address,
+        amount: Some(AmountInput::Text(amount)),
+        note_type: body.note_type,
+        challenge: body.challenge,
+        nonce: body.nonce,
+    };
+    enqueue_mint(faucet, &headers, peer, body)
+}
+
+/// Validates and queues a mint request, responds right away with the request id to poll
+fn enqueue_mint(
+    faucet: &ServedFaucet,
+    headers: &HeaderMap,
+    peer: SocketAddr,
+    body: MintBody,
+) -> Result<(StatusCode, Json<MintStatus>), MintError> {
+    let amount = body.amount.map(AmountInput::into_string);
+    let amount = faucet
+        .amounts
+        .resolve(amount.as_deref().filter(|amount| !amount.is_empty()))?;
+    let note_type = MINT_NOTE_TYPE_CONFIG
+        .resolve(body.note_type.as_deref())
+        .map_err(MintError::InvalidRequest)?;
+    let (_, account_id, _) = decode_target(&body.address)?;
+
+    match (body.challenge, body.nonce) {
+        (Some(challenge), Some(nonce)) => POW_GATE
+            .verify(account_id, &challenge, nonce)
+            .map_err(MintError::ProofOfWork)?,
+        _ => {
+            return Err(MintError::ProofOfWork(
+                "Missing proof of work, request a challenge from /pow?account_id=<address> and send challenge and nonce".to_string(),
+            ));
         }
     }
+
+    // every faucet has its own cooldown
+    let faucet_id = faucet.id.to_hex();
+    let rate_limit_keys = [
+        format!("{}:account:{}", faucet_id, account_id.to_hex()),
+        format!("{}:ip:{}", faucet_id, client_ip(headers, peer)),
+    ];
+    // the cooldown only starts once the request is stored, both in one transaction so two
+    // concurrent requests cannot both pass the check
+    let mut conn = Connection::open(MINT_DB).map_err(|err| MintError::Internal(err.to_string()))?;
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|err| MintError::Internal(err.to_string()))?;
+    let rate_limit =
+        check_rate_limit(&tx, &rate_limit_keys, faucet.cooldown_secs).map_err(|err| {
+            eprintln!("Rate limit check failed: {}", err);
+            MintError::Internal("Rate limit check failed".to_string())
+        })?;
+    if let Some(retry_at) = rate_limit {
+        return Err(MintError::RateLimited {
+            retry_at,
+            retry_after: retry_after_secs(retry_at),
+        });
+    }
+    let request_id = enqueue_mint_request(&tx, &faucet_id, &body.address, amount, note_type)
+        .map_err(MintError::Internal)?;
+    record_rate_limit(&tx, &rate_limit_keys, &request_id).map_err(MintError::Internal)?;
+    tx.commit()
+        .map_err(|err| MintError::Internal(format!("Failed to commit mint request {}", err)))?;
+    MintEvent::new(&request_id, STAGE_QUEUED).publish();
+    if count_queued(&conn).is_ok_and(|queued| queued >= MINT_BATCH_CONFIG.batch_size) {
+        MINT_FLUSH.notify_one();
+    }
+    Ok((StatusCode::ACCEPTED, Json(mint_status(&conn, &request_id)?)))
 }
 
-/// NEED TO ADD THIS TO CREATE CONTEXT FOR THE ASYNC RUNTIME IN THE CLIENT
-pub fn main() -> std::io::Result<()> {
-    dotenvy::dotenv().ok();
-    let n_workers = 20;
-    let pool = ThreadPool::new(n_workers);
+/// A faucet as listed by `/faucets`, amounts in base units
+#[derive(Serialize)]
+struct FaucetListing {
+    faucet_id: String,
+    symbol: String,
+    decimals: u8,
+    default_amount: u64,
+    max_amount: u64,
+    cooldown_secs: u64,
+    /// whether `POST /mint` mints from this faucet
+    default: bool,
+}
+
+async fn get_faucets() -> Json<Vec<FaucetListing>> {
+    Json(
+        FAUCETS
+            .iter()
+            .enumerate()
+            .map(|(i, faucet)| FaucetListing {
+                faucet_id: faucet.id.to_hex(),
+                symbol: faucet.config.symbol.clone(),
+                decimals: faucet.amounts.decimals,
+                default_amount: faucet.amounts.default_amount,
+                max_amount: faucet.amounts.max_amount,
+                cooldown_secs: faucet.cooldown_secs,
+                default: i == 0,
+            })
+            .collect(),
+    )
+}
 
-    // Start the fucking queue processor!
-    start_queue_processor();
-    println!("Queue processor started - will process batches every 10 seconds");
+async fn get_mint_request_status(
+    Path(request_id): Path<String>,
+) -> Result<Json<MintStatus>, MintError> {
+    let conn = Connection::open(MINT_DB).map_err(|err| MintError::Internal(err.to_string()))?;
+    Ok(Json(mint_status(&conn, &request_id)?))
+}
 
-    let listener = TcpListener::bind("127.0.0.1:9090")?;
-    println!("Server running on http://127.0.0.1:9090");
+/// Streams the progress of a mint request as server-sent events, ends once it is committed or failed
+async fn get_mint_request_events(
+    Path(request_id): Path<String>,
+) -> Result<
+    (
+        [(HeaderName, &'static str); 1],
+        Sse<impl Stream<Item = Result<Event, Infallible>>>,
+    ),
+    MintError,
+> {
+    // subscribe before reading the journal so no event is missed in between
+    let receiver = MINT_EVENTS.subscribe();
+    let conn = Connection::open(MINT_DB).map_err(|err| MintError::Internal(err.to_string()))?;
+    let record = get_mint_request(&conn, &request_id)
+        .map_err(MintError::Internal)?
+        .ok_or_else(|| MintError::NotFound("Mint request not found".to_string()))?;
+    let current = MintEvent::from_record(&record);
+    let done = current.is_final();
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                pool.execute(|| {
-                    handle_client(stream);
-                });
+    let updates = stream::unfold(
+        (receiver, request_id, done),
+        |(mut receiver, request_id, done)| async move {
+            if done {
+                return None;
             }
-            Err(e) => {
-                eprintln!("Connection failed: {}", e);
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if event.request_id == request_id => {
+                        let done = event.is_final();
+                        return Some((Ok(event.into_sse()), (receiver, request_id, done)));
+                    }
+                    Ok(_) => continue,
+                    // missed events are replaced by the journaled state of the request
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let record = Connection::open(MINT_DB)
+                            .map_err(|err| err.to_string())
+                            .and_then(|conn| get_mint_request(&conn, &request_id));
+                        if let Ok(Some(record)) = record {
+                            let event = MintEvent::from_record(&record);
+                            let done = event.is_final();
+                            return Some((Ok(event.into_sse()), (receiver, request_id, done)));
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
             }
+        },
+    );
+    let events = stream::once(async move { Ok(current.into_sse()) }).chain(updates);
+    // nginx buffers proxied responses by default, which would hold the events back
+    Ok((
+        [(HeaderName::from_static("x-accel-buffering"), "no")],
+        Sse::new(events).keep_alive(KeepAlive::default()),
+    ))
+}
+
+#[tokio::main]
+pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenvy::dotenv().ok();
+    let conn = Connection::open(MINT_DB).expect("Cannot open mint db");
+    create_rate_limit_table(&conn).expect("Failed to create rate limit table");
+    create_mint_requests_table(&conn).expect("Failed to create mint requests table");
+    // fail fast on a bad configuration instead of on the first request
+    lazy_static::initialize(&FAUCETS);
+    let requeued = requeue_interrupted(&conn).expect("Failed to requeue mint requests");
+    if requeued > 0 {
+        println!(
+            "Requeued {} mint requests interrupted by the last shutdown",
+            requeued
+        );
+    }
+    lazy_static::initialize(&CLIENT_DB);
+    lazy_static::initialize(&KEYSTORE);
+    println!(
+        "Keystore {} unlocked ({})",
+        KEYSTORE_DIR,
+        if KEYSTORE.is_encrypted() {
+            "encrypted"
+        } else {
+            "plaintext"
         }
+    );
+    lazy_static::initialize(&MINT_NOTE_TYPE_CONFIG);
+    for faucet in FAUCETS.iter() {
+        println!(
+            "Serving {} from faucet {}",
+            faucet.config.symbol,
+            faucet.id.to_hex()
+        );
     }
+    println!(
+        "Minting on {} via {} (prover: {} {}, note transport: {})",
+        NETWORK.name,
+        NETWORK.endpoint,
+        NETWORK.prover_mode,
+        NETWORK.tx_prover_url.as_deref().unwrap_or(""),
+        NETWORK.note_transport_url.as_deref().unwrap_or("disabled")
+    );
+
+    // the miden client is not `Send`, so minting runs on its own thread and runtime
+    start_queue_processor(*MINT_BATCH_CONFIG);
+    println!(
+        "Queue processor started - will process batches of up to {} requests every {:?}",
+        MINT_BATCH_CONFIG.batch_size, MINT_BATCH_CONFIG.flush_interval
+    );
+
+    let (cors_layer, cors_origins) = cors_layer();
+    let app = Router::new()
+        .route("/pow", get(get_pow_challenge))
+        .route("/faucets", get(get_faucets))
+        .route("/mint", post(mint))
+        .route("/mint/{faucet}/{address}/{amount}", post(mint_from_faucet))
+        .route("/mint/{request_id}", get(get_mint_request_status))
+        .route("/mint/{request_id}/events", get(get_mint_request_events))
+        .layer(ServiceBuilder::new().layer(cors_layer));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:9090").await?;
+    println!("Server running on http://127.0.0.1:9090");
+    println!("CORS origins: {}", cors_origins);
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -1,16 +1,28 @@
+use futures::{StreamExt, stream};
 use miden_client::{
     account::{AccountId, NetworkId},
     address::{Address, AddressId},
-    rpc::{Endpoint, GrpcClient, NodeRpcClient, domain::note::FetchedNote},
+    note::{NoteId, NoteType},
+    rpc::{GrpcClient, NodeRpcClient, domain::note::FetchedNote},
 };
 use miden_faucet_server::{
-    server::{APP_DB, FAUCET_ID},
-    tx_worker::{NoteData, SYNC_BLOCK_FILE, Transaction},
+    mint_queue::{create_mint_requests_table, get_minted_note},
+    network::NetworkConfig,
+    server::{APP_DB, FAUCETS},
+    tx_worker::{
+        AssetData, NoteData, SYNC_BLOCK_FILE, Transaction, get_indexed_block_below,
+        get_indexed_tip, get_sync_checkpoint, migrate_transactions_table, prefetch_blocks_from_env,
+        record_indexed_block, resolve_input_note, rollback_above,
+    },
+    utils::MINT_DB,
 };
-use miden_objects::block::ProvenBlock;
+use miden_objects::{block::ProvenBlock, transaction::TransactionHeader};
 use rusqlite::Connection;
 use std::error::Error;
-use std::{collections::BTreeSet, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
 
 pub fn get_accounts_to_be_tracked(conn: &Connection) -> BTreeSet<AccountId> {
     let mut stmt = conn
@@ -31,20 +43,79 @@ pub fn get_accounts_to_be_tracked(conn: &Connection) -> BTreeSet<AccountId> {
     for account in account_iter {
         accounts.insert(account.expect("Unable to get account"));
     }
-    accounts.insert(*FAUCET_ID);
+    for faucet in FAUCETS.iter() {
+        accounts.insert(faucet.account_id().expect("Invalid faucet id"));
+    }
     accounts
 }
 
-/// returns the total txs, total notes created, total faucet requests
+/// Output notes of `tx`. Metadata comes from the transaction header, public notes are fetched
+/// from the node for their recipient and assets.
+async fn index_output_notes(
+    rpc: &GrpcClient,
+    tx: &TransactionHeader,
+    network_id: &NetworkId,
+) -> Vec<NoteData> {
+    let public_note_ids: Vec<NoteId> = tx
+        .output_notes()
+        .iter()
+        .filter(|note| note.metadata().note_type() == NoteType::Public)
+        .map(|note| note.id())
+        .collect();
+    let mut public_notes = BTreeMap::new();
+    if !public_note_ids.is_empty() {
+        match rpc.get_notes_by_id(&public_note_ids).await {
+            Ok(fetched) => {
+                for note in fetched {
+                    if let FetchedNote::Public(note, _) = note {
+                        public_notes.insert(note.id(), note);
+                    }
+                }
+            }
+            Err(e) => println!("Error fetching notes of tx {}: {}", tx.id().to_hex(), e),
+        }
+    }
+    tx.output_notes()
+        .iter()
+        .enumerate()
+        .map(|(note_index, header)| {
+            let metadata = header.metadata();
+            let note = public_notes.get(&header.id());
+            NoteData {
+                note_id: header.id().to_hex(),
+                note_index: note_index as u32,
+                note_type: metadata.note_type().to_string(),
+                note_aux: metadata.aux().to_string(),
+                tag: Some(metadata.tag().into()),
+                sender: metadata.sender().to_bech32(network_id.clone()),
+                recipient: note.map(|note| note.recipient().digest().to_hex()),
+                assets: note.map(|note| note.assets().iter().map(AssetData::from).collect()),
+                nullifier: note.map(|note| note.nullifier().to_hex()),
+                consumed_by_tx: None,
+            }
+        })
+        .collect()
+}
+
+/// Indexes the transactions of `block` made by tracked accounts, along with the notes they
+/// created and consumed
 pub async fn update_db_raw_block(
     conn: &Connection,
+    mint_conn: &Connection,
     rpc: &GrpcClient,
     accounts_to_be_tracked: &BTreeSet<AccountId>,
+    network_id: &NetworkId,
     block: &ProvenBlock,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // check if the block contains updated accounts we are tracking
     let mut stmt = conn
-        .prepare("INSERT OR IGNORE INTO TRANSACTIONS_DETAIL (block_num, tx_id, tx_kind, sender, timestamp, note_id, note_type, note_aux) VALUES (?1, ?2,  ?3, ?4, ?5, ?6, ?7, ?8)")
+        .prepare("INSERT OR IGNORE INTO TRANSACTIONS_DETAIL (block_num, tx_id, tx_kind, sender, timestamp, faucet_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+        .expect("Unable to prepare statement");
+    let mut note_stmt = conn
+        .prepare("INSERT OR IGNORE INTO NOTES (note_id, tx_id, note_index, note_type, note_aux, tag, sender, recipient, assets, nullifier) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)")
+        .expect("Unable to prepare statement");
+    let mut input_note_stmt = conn
+        .prepare("INSERT OR IGNORE INTO INPUT_NOTES (nullifier, note_id, tx_id, created_by_tx) VALUES (?1, ?2, ?3, ?4)")
         .expect("Unable to prepare statement");
     let txs = block.transactions().as_slice();
     for tx in txs {
@@ -53,66 +124,128 @@ pub async fn update_db_raw_block(
         }
         let tx_id = tx.id().to_hex();
         let sender = tx.account_id();
-        let mut found_note = None;
-        let tx_kind = if sender == *FAUCET_ID {
+        // only transactions creating notes are mints, a faucet consuming its expired notes is a
+        // recall and indexed as a receive
+        let faucet_id = FAUCETS
+            .iter()
+            .any(|faucet| faucet.account_id().is_ok_and(|id| id == sender))
+            .then(|| sender.to_hex())
+            .filter(|_| !tx.output_notes().is_empty());
+        let tx_kind = if faucet_id.is_some() {
             "faucet_request"
         } else if !tx.output_notes().is_empty() {
-            let note_id = tx.output_notes()[0].id();
-            let note: Result<FetchedNote, _> = rpc.get_note_by_id(note_id).await;
-            found_note = match note {
-                Ok(FetchedNote::Public(note, _)) => Some(NoteData {
-                    note_id: note.id().to_hex(),
-                    note_type: note.metadata().note_type().to_string(),
-                    note_aux: note.metadata().aux().to_string(),
-                }),
-                Ok(FetchedNote::Private(note_id, note_aux, _)) => Some(NoteData {
-                    note_id: note_id.to_hex(),
-                    note_type: "private".to_string(),
-                    note_aux: note_aux.aux().to_string(),
-                }),
-                Err(e) => {
-                    println!(
-                        "Error fetching note {} at tx {}: {}",
-                        note_id.to_hex(),
-                        tx_id,
-                        e
-                    );
-                    None
-                }
-            };
             "send"
         } else if !tx.input_notes().is_empty() {
             "receive"
         } else {
-            return Err("Unknown tx kind".into());
+            // account updates without notes, e.g. a faucet key rotation, move no value
+            println!("Skipping tx {} without input or output notes", tx_id);
+            continue;
         };
+        let mut notes = index_output_notes(rpc, tx, network_id).await;
+        if faucet_id.is_some() {
+            // private mints are only known to the mint server that made them
+            for note in notes.iter_mut().filter(|note| note.assets.is_none()) {
+                if let Some((amount, nullifier)) = get_minted_note(mint_conn, &note.note_id)? {
+                    note.assets = Some(vec![AssetData {
+                        faucet_id: sender.to_hex(),
+                        amount: Some(amount),
+                        asset_id: None,
+                    }]);
+                    note.nullifier = nullifier;
+                }
+            }
+        }
+        // notes created earlier in this block are indexed already, so they resolve too
+        let mut input_notes = vec![];
+        for input_note in tx.input_notes().iter() {
+            input_notes.push(resolve_input_note(
+                conn,
+                &input_note.nullifier().to_hex(),
+                input_note.header().map(|header| header.id().to_hex()),
+            )?);
+        }
 
         let tx = Transaction {
             tx_id,
             tx_kind: tx_kind.to_string(),
-            sender: sender.to_bech32(NetworkId::Testnet),
+            sender: sender.to_bech32(network_id.clone()),
             block_num: block.header().block_num().as_u32(),
-            note_id: found_note,
+            notes: vec![],
+            input_notes: vec![],
+            value: vec![],
             timestamp: block.header().timestamp(),
+            confirmations: 0,
+            confirmed: false,
+            faucet_id,
         };
+        let tx_id = tx.tx_id.clone();
         stmt.execute(tx.into_sql_value())?;
+        for note in notes {
+            note_stmt.execute(note.into_sql_value(&tx_id))?;
+        }
+        for input_note in input_notes {
+            input_note_stmt.execute(input_note.into_sql_value(&tx_id))?;
+        }
     }
     Ok(())
 }
 
+/// Highest indexed block below `below` that is still part of the node's chain, 0 if none is
+async fn find_fork_point(
+    conn: &Connection,
+    rpc: &GrpcClient,
+    below: u32,
+    chain_tip: u32,
+) -> Result<u32, Box<dyn Error>> {
+    let mut below = below;
+    while let Some((block_num, commitment)) = get_indexed_block_below(conn, below)? {
+        below = block_num;
+        // a block above the tip was dropped by a reorg to a shorter chain
+        if block_num > chain_tip {
+            continue;
+        }
+        let (header, _) = rpc
+            .get_block_header_by_number(Some(block_num.into()), false)
+            .await?;
+        if header.commitment().to_hex() == commitment {
+            return Ok(block_num);
+        }
+    }
+    Ok(0)
+}
+
+/// Drops everything indexed above `fork_point`, returns the next block to index
+fn roll_back(conn: &Connection, fork_point: u32) -> Result<u32, String> {
+    let dropped = rollback_above(conn, fork_point)?;
+    if fork_point == 0 {
+        // nothing indexed is left, the legacy checkpoint must not resume past the fork
+        std::fs::remove_file(SYNC_BLOCK_FILE).ok();
+    }
+    println!(
+        "Chain reorganized above block {}, rolled back {} transactions",
+        fork_point, dropped
+    );
+    Ok(fork_point + 1)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenvy::dotenv()?;
     println!("WORKER STARTED");
     let conn = Connection::open(APP_DB).expect("Cannot open db");
-    let rpc = GrpcClient::new(&Endpoint::testnet(), 100_000);
+    migrate_transactions_table(&conn)?;
+    let mint_conn = Connection::open(MINT_DB).expect("Cannot open mint db");
+    create_mint_requests_table(&mint_conn)?;
+    let network = NetworkConfig::from_env()?;
+    println!("Indexing {} via {}", network.name, network.endpoint);
+    let rpc = GrpcClient::new(&network.endpoint, 100_000);
     let empty_btree_set = BTreeSet::new();
+    let prefetch_blocks = prefetch_blocks_from_env();
+    println!("Fetching up to {} blocks in parallel", prefetch_blocks);
 
-    // get the last sync block
-    let mut last_sync_block = std::fs::read_to_string(SYNC_BLOCK_FILE)
-        .ok()
-        .and_then(|s| s.trim().parse::<u32>().ok())
-        .unwrap_or(1);
+    // the next block to index, everything below it is applied
+    let mut last_sync_block = get_sync_checkpoint(&conn)?;
     loop {
         // find accounts to be tracked
         let accounts_to_be_tracked = get_accounts_to_be_tracked(&conn);
@@ -122,40 +255,90 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .await?
             .chain_tip
             .as_u32();
-        let mut i = last_sync_block;
-        while i <= latest_block {
-            if i.is_multiple_of(100) {
-                println!(
-                    "Progress: {:.2}%, Block: {}/{}",
-                    (i as f64 / latest_block as f64) * 100.0,
-                    i,
-                    latest_block
-                );
+        // blocks we indexed can be replaced even when no new block follows them yet
+        if let Some(tip) = get_indexed_tip(&conn)? {
+            let fork_point = find_fork_point(&conn, &rpc, tip + 1, latest_block).await?;
+            if fork_point < tip {
+                last_sync_block = roll_back(&conn, fork_point)?;
             }
-            let raw_block = match rpc.get_block_by_number(i.into()).await {
+        }
+        // commitment of the block before the next one, every block must build on it
+        let mut parent_commitment = get_indexed_block_below(&conn, last_sync_block)?
+            .filter(|(block_num, _)| *block_num + 1 == last_sync_block)
+            .map(|(_, commitment)| commitment);
+        let started = Instant::now();
+        let first_block = last_sync_block;
+        // blocks are fetched concurrently but come out of `buffered` in order
+        let mut blocks = stream::iter(last_sync_block..=latest_block)
+            .map(|block_num| {
+                let rpc = &rpc;
+                async move { (block_num, rpc.get_block_by_number(block_num.into()).await) }
+            })
+            .buffered(prefetch_blocks);
+        while let Some((block_num, raw_block)) = blocks.next().await {
+            let raw_block = match raw_block {
                 Ok(block) => block,
                 Err(e) => {
-                    panic!("Error fetching block {}: {}", i, e);
+                    // blocks below this one are applied, resume from it on the next round
+                    eprintln!("Error fetching block {}: {}", block_num, e);
+                    // the next round checks its first block against the last applied one
+                    if let Some(commitment) = &parent_commitment
+                        && last_sync_block > first_block
+                    {
+                        record_indexed_block(&conn, last_sync_block - 1, commitment)?;
+                    }
+                    break;
                 }
             };
-            let updated_accounts: Vec<AccountId> = raw_block
+            let header = raw_block.header();
+            if parent_commitment
+                .as_ref()
+                .is_some_and(|parent| *parent != header.prev_block_commitment().to_hex())
+            {
+                println!(
+                    "Block {} does not build on the indexed chain, looking for the fork point",
+                    block_num
+                );
+                let fork_point = find_fork_point(&conn, &rpc, block_num, latest_block).await?;
+                last_sync_block = roll_back(&conn, fork_point)?;
+                break;
+            }
+            let commitment = header.commitment().to_hex();
+            let updated_accounts: BTreeSet<AccountId> = raw_block
                 .updated_accounts()
                 .iter()
                 .map(|acc| acc.account_id())
                 .collect();
-            let other: BTreeSet<AccountId> = updated_accounts.into_iter().collect();
-            if accounts_to_be_tracked.is_disjoint(&other) {
-                i += 1;
-                continue;
+            if !accounts_to_be_tracked.is_disjoint(&updated_accounts) {
+                // the block's rows and the checkpoint past it are written together
+                let db_tx = conn.unchecked_transaction()?;
+                update_db_raw_block(
+                    &db_tx,
+                    &mint_conn,
+                    &rpc,
+                    &accounts_to_be_tracked,
+                    &network.network_id,
+                    &raw_block,
+                )
+                .await?;
+                record_indexed_block(&db_tx, block_num, &commitment)?;
+                db_tx.commit()?;
+            } else if block_num.is_multiple_of(100) || block_num == latest_block {
+                record_indexed_block(&conn, block_num, &commitment)?;
+            }
+            parent_commitment = Some(commitment);
+            last_sync_block = block_num + 1;
+            if block_num.is_multiple_of(100) {
+                let applied = last_sync_block - first_block;
+                println!(
+                    "Progress: {:.2}%, Block: {}/{}, {:.1} blocks/sec",
+                    (block_num as f64 / latest_block as f64) * 100.0,
+                    block_num,
+                    latest_block,
+                    applied as f64 / started.elapsed().as_secs_f64()
+                );
             }
-            update_db_raw_block(&conn, &rpc, &accounts_to_be_tracked, &raw_block).await?;
-            i += 1;
-            std::fs::write(SYNC_BLOCK_FILE, i.to_string())
-                .expect("Failed to write last_sync_block");
         }
-        last_sync_block = latest_block;
-        std::fs::write(SYNC_BLOCK_FILE, last_sync_block.to_string())
-            .expect("Failed to write last_sync_block");
         tokio::time::sleep(Duration::from_secs(3)).await;
     }
 }
@@ -1,55 +1,397 @@
+use std::{path::Path, sync::Arc};
+
 use lazy_static::lazy_static;
 use miden_client::{
     Felt,
     account::{
-        AccountBuilder, AccountStorageMode, AccountType,
-        component::{AuthRpoFalcon512, BasicFungibleFaucet},
+        AccountBuilder, AccountId, AccountStorageMode, AccountType,
+        component::{BasicFungibleFaucet, BasicWallet},
     },
-    asset::TokenSymbol,
+    asset::{FungibleAsset, TokenSymbol},
     auth::AuthSecretKey,
     crypto::rpo_falcon512::SecretKey,
-    keystore::FilesystemKeyStore,
-    rpc::Endpoint,
 };
 use rand_core::TryRngCore;
+use serde::{Deserialize, Serialize};
 
-use crate::utils::init_client;
+use crate::{
+    amount::{format_token_amount, parse_token_amount},
+    key_rotation::RotatableAuthRpoFalcon512,
+    keystore::FaucetKeyStore,
+    network::NetworkConfig,
+    reclaim::RecallBurn,
+    utils::init_client,
+};
 
 lazy_static! {
     pub static ref CLIENT_DB: String = std::env::var("CLIENT_DB").unwrap();
 }
 
-pub async fn create_new_faucet(endpoint: Endpoint) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = init_client(&CLIENT_DB, endpoint).await;
-    let keystore = FilesystemKeyStore::new("./keystore".into())?;
+/// File written by `create-faucet`, overridden by `FAUCET_CONFIG`
+pub const DEFAULT_FAUCET_CONFIG_FILE: &str = "./faucet.json";
+pub const DEFAULT_TOKEN_SYMBOL: &str = "MDN";
+pub const DEFAULT_TOKEN_DECIMALS: u8 = 8;
+/// Default max supply in whole tokens, 10 billion tokens
+pub const DEFAULT_MAX_SUPPLY: &str = "10000000000";
+
+/// A faucet created by `create-faucet`, the faucet config file holds a list of them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FaucetConfig {
+    pub faucet_id: String,
+    pub network: String,
+    pub symbol: String,
+    pub decimals: u8,
+    /// in base units
+    pub max_supply: u64,
+    pub storage_mode: String,
+    /// per faucet overrides of `MINT_DEFAULT_AMOUNT`, `MINT_MAX_AMOUNT` (whole tokens) and
+    /// `MINT_COOLDOWN_SECS`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint_default_amount: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint_max_amount: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint_cooldown_secs: Option<u64>,
+}
+
+/// The config file is a list of faucets, older files hold a single one
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FaucetConfigFile {
+    Many(Vec<FaucetConfig>),
+    One(FaucetConfig),
+}
+
+pub fn faucet_config_path() -> String {
+    std::env::var("FAUCET_CONFIG").unwrap_or_else(|_| DEFAULT_FAUCET_CONFIG_FILE.to_string())
+}
+
+impl FaucetConfig {
+    pub fn account_id(&self) -> Result<AccountId, String> {
+        AccountId::from_hex(&self.faucet_id)
+            .map_err(|err| format!("Invalid faucet id {}: {}", self.faucet_id, err))
+    }
+
+    /// Reads every faucet in the config file, empty if it does not exist
+    pub fn load_all() -> Result<Vec<Self>, String> {
+        let path = faucet_config_path();
+        if !Path::new(&path).exists() {
+            return Ok(vec![]);
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read faucet config {}: {}", path, err))?;
+        match serde_json::from_str(&contents) {
+            Ok(FaucetConfigFile::Many(faucets)) => Ok(faucets),
+            Ok(FaucetConfigFile::One(faucet)) => Ok(vec![faucet]),
+            Err(err) => Err(format!("Invalid faucet config {}: {}", path, err)),
+        }
+    }
+
+    pub fn save_all(faucets: &[Self]) -> Result<(), String> {
+        let path = faucet_config_path();
+        let contents =
+            serde_json::to_string_pretty(faucets).expect("faucet config is serializable");
+        std::fs::write(&path, contents)
+            .map_err(|err| format!("Failed to write faucet config {}: {}", path, err))
+    }
+
+    /// Whether `key` is this faucet's hex id or (case insensitive) symbol
+    pub fn matches(&self, key: &str) -> bool {
+        self.faucet_id.eq_ignore_ascii_case(key) || self.symbol.eq_ignore_ascii_case(key)
+    }
+}
+
+/// Faucets served by this deployment.
+///
+/// These are the faucets in the config file, unless `FAUCET_ID` (comma separated hex ids) is set,
+/// in which case only those are served. Ids missing from the config file take their symbol and
+/// decimals from `FAUCET_SYMBOL` and `FAUCET_DECIMALS`. The first faucet is the default one.
+pub fn configured_faucets() -> Result<Vec<FaucetConfig>, String> {
+    let from_file = FaucetConfig::load_all()?;
+    let faucets = match std::env::var("FAUCET_ID") {
+        Ok(faucet_ids) if !faucet_ids.trim().is_empty() => faucet_ids
+            .split(',')
+            .map(str::trim)
+            .filter(|faucet_id| !faucet_id.is_empty())
+            .map(|faucet_id| {
+                from_file
+                    .iter()
+                    .find(|faucet| faucet.faucet_id.eq_ignore_ascii_case(faucet_id))
+                    .cloned()
+                    .unwrap_or_else(|| env_faucet(faucet_id))
+            })
+            .collect(),
+        _ => from_file,
+    };
+    if faucets.is_empty() {
+        return Err(format!(
+            "FAUCET_ID is not set and there is no faucet config at {}, run create-faucet first",
+            faucet_config_path()
+        ));
+    }
+    for faucet in &faucets {
+        faucet.account_id()?;
+    }
+    Ok(faucets)
+}
+
+/// Faucet only known through `FAUCET_ID`
+fn env_faucet(faucet_id: &str) -> FaucetConfig {
+    FaucetConfig {
+        faucet_id: faucet_id.to_string(),
+        network: std::env::var("MIDEN_NETWORK").unwrap_or_default(),
+        symbol: std::env::var("FAUCET_SYMBOL").unwrap_or_else(|_| DEFAULT_TOKEN_SYMBOL.to_string()),
+        decimals: std::env::var("FAUCET_DECIMALS")
+            .ok()
+            .and_then(|decimals| decimals.trim().parse().ok())
+            .unwrap_or(DEFAULT_TOKEN_DECIMALS),
+        max_supply: 0,
+        storage_mode: AccountStorageMode::Public.to_string(),
+        mint_default_amount: None,
+        mint_max_amount: None,
+        mint_cooldown_secs: None,
+    }
+}
+
+/// Validated token parameters for a new faucet
+#[derive(Debug, Clone)]
+pub struct FaucetParams {
+    pub symbol: TokenSymbol,
+    pub decimals: u8,
+    /// in base units
+    pub max_supply: u64,
+    pub storage_mode: AccountStorageMode,
+}
+
+impl FaucetParams {
+    /// Validates the token parameters, `max_supply` is given in whole tokens
+    pub fn new(
+        symbol: &str,
+        decimals: &str,
+        max_supply: &str,
+        storage_mode: &str,
+    ) -> Result<Self, String> {
+        let symbol = symbol.trim().to_uppercase();
+        let token_symbol = TokenSymbol::new(&symbol).map_err(|err| {
+            format!(
+                "Invalid symbol {}: {}, use 1 to {} letters A-Z",
+                symbol,
+                err,
+                TokenSymbol::MAX_SYMBOL_LENGTH
+            )
+        })?;
+        let decimals: u8 = decimals
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid decimals {}", decimals))?;
+        if decimals > BasicFungibleFaucet::MAX_DECIMALS {
+            return Err(format!(
+                "Decimals {} exceed the maximum of {}",
+                decimals,
+                BasicFungibleFaucet::MAX_DECIMALS
+            ));
+        }
+        let max_supply = parse_token_amount(max_supply, decimals)
+            .map_err(|err| format!("Invalid max supply: {}", err))?;
+        if max_supply == 0 {
+            return Err("Max supply must be greater than 0".to_string());
+        }
+        if max_supply > FungibleAsset::MAX_AMOUNT {
+            return Err(format!(
+                "Max supply exceeds the maximum of {} tokens with {} decimals",
+                format_token_amount(FungibleAsset::MAX_AMOUNT, decimals),
+                decimals
+            ));
+        }
+        let storage_mode = match storage_mode.trim().to_lowercase().as_str() {
+            "public" => AccountStorageMode::Public,
+            "network" => AccountStorageMode::Network,
+            other => {
+                return Err(format!(
+                    "Unsupported storage mode {}, use public or network",
+                    other
+                ));
+            }
+        };
+        Ok(Self {
+            symbol: token_symbol,
+            decimals,
+            max_supply,
+            storage_mode,
+        })
+    }
+
+    /// Parses `--symbol`, `--decimals`, `--max-supply` (whole tokens) and `--storage-mode`,
+    /// falling back to the defaults for missing ones
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut symbol = DEFAULT_TOKEN_SYMBOL.to_string();
+        let mut decimals = DEFAULT_TOKEN_DECIMALS.to_string();
+        let mut max_supply = DEFAULT_MAX_SUPPLY.to_string();
+        let mut storage_mode = "public".to_string();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let target = match flag.as_str() {
+                "--symbol" => &mut symbol,
+                "--decimals" => &mut decimals,
+                "--max-supply" => &mut max_supply,
+                "--storage-mode" => &mut storage_mode,
+                _ => return Err(format!("Unknown argument {}", flag)),
+            };
+            *target = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {}", flag))?
+                .clone();
+        }
+        Self::new(&symbol, &decimals, &max_supply, &storage_mode)
+    }
+}
+
+pub async fn create_new_faucet(
+    network: &NetworkConfig,
+    params: &FaucetParams,
+    keystore: Arc<FaucetKeyStore>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let faucet_component =
+        BasicFungibleFaucet::new(params.symbol, params.decimals, Felt::new(params.max_supply))?;
+
+    let mut client = init_client(&CLIENT_DB, network, keystore.clone()).await;
     let mut init_seed = [0u8; 32];
     client.rng().try_fill_bytes(&mut init_seed)?;
 
-    // Faucet parameters
-    let symbol = TokenSymbol::new("MDN").unwrap();
-    let decimals = 8;
-    let max_supply = Felt::new(1_000_000_000_000_000_000u64); // 100 million MID with 8 decimals
-
     // Generate key pair
     let key_pair = SecretKey::with_rng(client.rng());
 
     // Build the account
     let builder = AccountBuilder::new(init_seed)
         .account_type(AccountType::FungibleFaucet)
-        .storage_mode(AccountStorageMode::Public)
-        .with_auth_component(AuthRpoFalcon512::new(key_pair.public_key().into()))
-        .with_component(BasicFungibleFaucet::new(symbol, decimals, max_supply).unwrap());
-    let faucet_account = builder.build().unwrap();
+        .storage_mode(params.storage_mode)
+        // like AuthRpoFalcon512, but the key can be replaced with `rotate-faucet-key`
+        .with_auth_component(RotatableAuthRpoFalcon512::new(key_pair.public_key().into()))
+        .with_component(faucet_component)
+        // lets the faucet consume its own reclaimable notes once they expire
+        .with_component(BasicWallet)
+        // burns the tokens of recalled notes
+        .with_component(RecallBurn);
+    let faucet_account = builder.build()?;
 
     // Add the faucet to the client
     client.add_account(&faucet_account, false).await?;
 
     // Add the key pair to the keystore
-    keystore
-        .add_key(&AuthSecretKey::RpoFalcon512(key_pair))
-        .unwrap();
+    keystore.add_key(&AuthSecretKey::RpoFalcon512(key_pair))?;
+
+    let config = FaucetConfig {
+        faucet_id: faucet_account.id().to_hex(),
+        network: network.name.clone(),
+        symbol: params.symbol.to_string()?,
+        decimals: params.decimals,
+        max_supply: params.max_supply,
+        storage_mode: params.storage_mode.to_string(),
+        mint_default_amount: None,
+        mint_max_amount: None,
+        mint_cooldown_secs: None,
+    };
+    // a faucet with the same symbol is replaced, `create-faucet` refuses that without --force
+    let mut faucets = FaucetConfig::load_all()?;
+    faucets.retain(|faucet| !faucet.symbol.eq_ignore_ascii_case(&config.symbol));
+    faucets.push(config.clone());
+    FaucetConfig::save_all(&faucets)?;
 
     println!("Faucet account ID: {:?}", faucet_account.id().to_hex());
+    println!(
+        "Faucet address on {}: {}",
+        network.name,
+        faucet_account.id().to_bech32(network.network_id.clone())
+    );
+    println!(
+        "{} with {} decimals and a max supply of {} tokens, added to {}",
+        config.symbol,
+        config.decimals,
+        format_token_amount(config.max_supply, config.decimals),
+        faucet_config_path()
+    );
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn accepts_valid_parameters() {
+        let params = FaucetParams::new(" mdn ", "8", "10.5", "Network").unwrap();
+        assert_eq!(params.symbol, TokenSymbol::new("MDN").unwrap());
+        assert_eq!(params.decimals, 8);
+        assert_eq!(params.max_supply, 1_050_000_000);
+        assert_eq!(params.storage_mode, AccountStorageMode::Network);
+    }
+
+    #[test]
+    fn rejects_invalid_symbols() {
+        for symbol in ["", "TOOLONG", "MD1", "M-N"] {
+            assert!(
+                FaucetParams::new(symbol, "8", "100", "public").is_err(),
+                "{:?} should be rejected",
+                symbol
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_decimals_above_the_maximum() {
+        let max = BasicFungibleFaucet::MAX_DECIMALS;
+        assert!(FaucetParams::new("MDN", &max.to_string(), "1", "public").is_ok());
+        assert!(FaucetParams::new("MDN", &(max + 1).to_string(), "1", "public").is_err());
+        assert!(FaucetParams::new("MDN", "-1", "1", "public").is_err());
+        assert!(FaucetParams::new("MDN", "eight", "1", "public").is_err());
+    }
+
+    #[test]
+    fn rejects_max_supply_above_the_asset_maximum() {
+        let max = format_token_amount(FungibleAsset::MAX_AMOUNT, 12);
+        let params = FaucetParams::new("MDN", "12", &max, "public").unwrap();
+        assert_eq!(params.max_supply, FungibleAsset::MAX_AMOUNT);
+
+        let above = format_token_amount(FungibleAsset::MAX_AMOUNT + 1, 12);
+        assert!(FaucetParams::new("MDN", "12", &above, "public").is_err());
+        assert!(FaucetParams::new("MDN", "0", &u64::MAX.to_string(), "public").is_err());
+        assert!(FaucetParams::new("MDN", "8", "0", "public").is_err());
+        assert!(FaucetParams::new("MDN", "2", "1.001", "public").is_err());
+    }
+
+    #[test]
+    fn accepts_public_and_network_storage_only() {
+        let params = FaucetParams::new("MDN", "8", "100", "PUBLIC").unwrap();
+        assert_eq!(params.storage_mode, AccountStorageMode::Public);
+        assert!(FaucetParams::new("MDN", "8", "100", "private").is_err());
+        assert!(FaucetParams::new("MDN", "8", "100", "").is_err());
+    }
+
+    #[test]
+    fn parses_arguments_with_defaults() {
+        let params = FaucetParams::from_args(&[]).unwrap();
+        assert_eq!(
+            params.symbol,
+            TokenSymbol::new(DEFAULT_TOKEN_SYMBOL).unwrap()
+        );
+        assert_eq!(params.decimals, DEFAULT_TOKEN_DECIMALS);
+        assert_eq!(
+            params.max_supply,
+            parse_token_amount(DEFAULT_MAX_SUPPLY, DEFAULT_TOKEN_DECIMALS).unwrap()
+        );
+        assert_eq!(params.storage_mode, AccountStorageMode::Public);
+
+        let params =
+            FaucetParams::from_args(&args(&["--symbol", "eth", "--decimals", "6"])).unwrap();
+        assert_eq!(params.symbol, TokenSymbol::new("ETH").unwrap());
+        assert_eq!(params.decimals, 6);
+
+        assert!(FaucetParams::from_args(&args(&["--symbol"])).is_err());
+        assert!(FaucetParams::from_args(&args(&["--supply", "1"])).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Faucet signing key rotation.
+//!
+//! Faucets are created with [`RotatableAuthRpoFalcon512`], which authenticates transactions like
+//! miden's `AuthRpoFalcon512` and also exports `rotate_public_key` to replace the stored public
+//! key. Signatures are always checked against the key the account started the transaction with,
+//! so only the current key can rotate to a new one.
+use std::{
+    error::Error,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use miden_client::{
+    BlockNumber, Word,
+    account::{AccountComponent, AccountId, StorageSlot},
+    assembly::{DefaultSourceManager, Library, LibraryPath, Module, ModuleKind},
+    auth::{AuthSecretKey, PublicKeyCommitment},
+    crypto::rpo_falcon512::SecretKey,
+    store::TransactionFilter,
+    transaction::{
+        TransactionKernel, TransactionRequestBuilder, TransactionResult, TransactionStatus,
+    },
+};
+
+use crate::{
+    faucet::{CLIENT_DB, FaucetConfig},
+    keystore::FaucetKeyStore,
+    network::NetworkConfig,
+    utils::FaucetClient,
+};
+
+/// Path of the faucet auth library, its procedures are called as `::miden_faucet::auth::<name>`
+pub const FAUCET_AUTH_LIBRARY_PATH: &str = "miden_faucet::auth";
+/// How long `rotate-faucet-key` waits for the rotation to commit
+pub const ROTATION_COMMIT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const ROTATION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+const FAUCET_AUTH_CODE: &str = "
+use.miden::active_account
+use.miden::native_account
+use.miden::auth::rpo_falcon512
+
+# slot of the public key commitment in this component's storage
+const.PUBLIC_KEY_SLOT=0
+
+#! Authenticates the transaction with the public key the account had when it started, so a
+#! rotation is signed by the key it replaces.
+#!
+#! Inputs:  [AUTH_ARGS, pad(12)]
+#! Outputs: [pad(16)]
+export.auth_tx_rpo_falcon512_rotatable
+    dropw
+    push.PUBLIC_KEY_SLOT exec.active_account::get_initial_item
+    # => [PUB_KEY, pad(16)]
+
+    exec.rpo_falcon512::authenticate_transaction
+end
+
+#! Replaces the public key the next transactions are authenticated with.
+#!
+#! Inputs:  [NEW_PUB_KEY, pad(12)]
+#! Outputs: [pad(16)]
+export.rotate_public_key
+    push.PUBLIC_KEY_SLOT exec.native_account::set_item
+    # => [OLD_PUB_KEY, pad(12)]
+
+    dropw
+end
+";
+
+lazy_static! {
+    static ref FAUCET_AUTH_LIBRARY: Library = {
+        let source_manager = Arc::new(DefaultSourceManager::default());
+        let path = LibraryPath::new(FAUCET_AUTH_LIBRARY_PATH).expect("valid library path");
+        let module = Module::parser(ModuleKind::Library)
+            .parse_str(path, FAUCET_AUTH_CODE, source_manager.as_ref())
+            .expect("faucet auth code parses");
+        TransactionKernel::assembler_with_source_manager(source_manager)
+            .assemble_library([module])
+            .expect("faucet auth code compiles")
+    };
+}
+
+/// RpoFalcon512 auth component whose public key can be rotated, see the module docs
+pub struct RotatableAuthRpoFalcon512 {
+    pub_key: PublicKeyCommitment,
+}
+
+impl RotatableAuthRpoFalcon512 {
+    pub fn new(pub_key: PublicKeyCommitment) -> Self {
+        Self { pub_key }
+    }
+
+    /// MAST root of `rotate_public_key`, present in the code of every faucet that can rotate
+    pub fn rotate_public_key_digest() -> Word {
+        FAUCET_AUTH_LIBRARY
+            .get_procedure_root_by_name(format!("{}::rotate_public_key", FAUCET_AUTH_LIBRARY_PATH))
+            .expect("faucet auth library exports rotate_public_key")
+    }
+}
+
+impl From<RotatableAuthRpoFalcon512> for AccountComponent {
+    fn from(auth: RotatableAuthRpoFalcon512) -> Self {
+        AccountComponent::new(
+            FAUCET_AUTH_LIBRARY.clone(),
+            vec![StorageSlot::Value(auth.pub_key.into())],
+        )
+        .expect("faucet auth component is valid")
+        .with_supports_all_types()
+    }
+}
+
+/// Replaces the signing key of `faucet` with a new one.
+///
+/// The new key is stored before the update is submitted and the old one is removed from the
+/// keystore only once the update commits. If it is discarded the new key is removed instead, if
+/// it does not commit within [`ROTATION_COMMIT_TIMEOUT`] both keys are kept.
+pub async fn rotate_faucet_key(
+    network: NetworkConfig,
+    faucet: &FaucetConfig,
+    keystore: Arc<FaucetKeyStore>,
+) -> Result<(), Box<dyn Error>> {
+    let faucet_id = faucet.account_id()?;
+    let mut faucet_client =
+        FaucetClient::new(&CLIENT_DB, network, vec![faucet_id], keystore.clone()).await?;
+    faucet_client.sync().await?;
+
+    let account = faucet_client
+        .client
+        .get_account(faucet_id)
+        .await?
+        .ok_or_else(|| format!("Faucet {} is not tracked by the client", faucet.faucet_id))?;
+    let rotate_digest = RotatableAuthRpoFalcon512::rotate_public_key_digest();
+    // the procedure's storage offset is where the component's public key slot ended up
+    let key_slot = account
+        .account()
+        .code()
+        .procedures()
+        .iter()
+        .find(|procedure| *procedure.mast_root() == rotate_digest)
+        .map(|procedure| procedure.storage_offset())
+        .ok_or_else(|| {
+            format!(
+                "Faucet {} was created before key rotation was supported, its key cannot be rotated",
+                faucet.faucet_id
+            )
+        })?;
+    let old_pub_key = account.account().storage().get_item(key_slot)?;
+    if keystore.get_key(old_pub_key)?.is_none() {
+        return Err(format!(
+            "The current key of faucet {} ({}) is not in the keystore",
+            faucet.faucet_id,
+            old_pub_key.to_hex()
+        )
+        .into());
+    }
+
+    let new_key = SecretKey::with_rng(faucet_client.client.rng());
+    let new_pub_key = Word::from(PublicKeyCommitment::from(new_key.public_key()));
+    keystore.add_key(&AuthSecretKey::RpoFalcon512(new_key))?;
+
+    // nothing reached the node if this fails, so the new key is of no use
+    let (tx_result, submission_height) =
+        match submit_rotation(&mut faucet_client, faucet_id, new_pub_key).await {
+            Ok(submitted) => submitted,
+            Err(err) => {
+                keystore.remove_key(new_pub_key)?;
+                return Err(err);
+            }
+        };
+    let tx_id = tx_result.executed_transaction().id();
+    faucet_client
+        .client
+        .apply_transaction(&tx_result, submission_height)
+        .await
+        .map_err(|err| {
+            format!(
+                "Key rotation {} submitted but not applied, both keys are kept: {}",
+                tx_id.to_hex(),
+                err
+            )
+        })?;
+    println!(
+        "Key rotation {} submitted for faucet {}, waiting for it to commit",
+        tx_id.to_hex(),
+        faucet.faucet_id
+    );
+
+    let deadline = Instant::now() + ROTATION_COMMIT_TIMEOUT;
+    while Instant::now() < deadline {
+        tokio::time::sleep(ROTATION_POLL_INTERVAL).await;
+        faucet_client.sync().await?;
+        let status = faucet_client
+            .client
+            .get_transactions(TransactionFilter::Ids(vec![tx_id]))
+            .await?
+            .pop()
+            .map(|record| record.status);
+        match status {
+            Some(TransactionStatus::Committed { block_number, .. }) => {
+                keystore.remove_key(old_pub_key)?;
+                println!(
+                    "Key rotation committed at block {}, faucet {} now signs with {}",
+                    block_number,
+                    faucet.faucet_id,
+                    new_pub_key.to_hex()
+                );
+                return Ok(());
+            }
+            Some(TransactionStatus::Discarded(cause)) => {
+                keystore.remove_key(new_pub_key)?;
+                return Err(format!("Key rotation discarded: {:?}", cause).into());
+            }
+            _ => {}
+        }
+    }
+    Err(format!(
+        "Key rotation {} did not commit within {:?}, both keys are kept, remove the old key {} once it commits",
+        tx_id.to_hex(),
+        ROTATION_COMMIT_TIMEOUT,
+        old_pub_key.to_hex()
+    )
+    .into())
+}
+
+/// Executes, proves and submits the transaction that sets the faucet's public key to `new_pub_key`
+async fn submit_rotation(
+    faucet_client: &mut FaucetClient,
+    faucet_id: AccountId,
+    new_pub_key: Word,
+) -> Result<(TransactionResult, BlockNumber), Box<dyn Error>> {
+    let client = &mut faucet_client.client;
+    let tx_script = client
+        .script_builder()
+        .with_dynamically_linked_library(&FAUCET_AUTH_LIBRARY)?
+        .compile_tx_script(format!(
+            "begin
+                push.{new_pub_key}
+                call.::{FAUCET_AUTH_LIBRARY_PATH}::rotate_public_key
+                dropw
+            end"
+        ))?;
+    let transaction_request = TransactionRequestBuilder::new()
+        .custom_script(tx_script)
+        .build()?;
+    let tx_result = client
+        .execute_transaction(faucet_id, transaction_request)
+        .await?;
+    let (proven_transaction, prover) = faucet_client.prover.prove(client, &tx_result).await?;
+    println!("Key rotation proven by the {} prover", prover);
+    let submission_height = client
+        .submit_proven_transaction(proven_transaction, &tx_result)
+        .await?;
+    Ok((tx_result, submission_height))
+}
@@ -0,0 +1,502 @@
+//! Keystore holding the faucet signing keys.
+//!
+//! Keys live one per file in [`KEYSTORE_DIR`], named like miden's `FilesystemKeyStore` names them.
+//! Once the keystore is encrypted, every key file holds `enc:v1:<nonce>:<ciphertext>` sealed with
+//! XChaCha20-Poly1305 under a key derived (PBKDF2-HMAC-SHA256) from `KEYSTORE_PASSPHRASE` or the
+//! contents of `KEYSTORE_KEY_FILE`. The salt and a check value to verify the passphrase on unlock
+//! are kept in [`KEYSTORE_HEADER_FILE`].
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, Payload},
+};
+use miden_client::{
+    AuthenticationError, Deserializable, Serializable, Word,
+    auth::{
+        AuthSecretKey, PublicKeyCommitment, Signature, SigningInputs, TransactionAuthenticator,
+    },
+    keystore::FilesystemKeyStore,
+};
+use pbkdf2::pbkdf2_hmac;
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+pub const KEYSTORE_DIR: &str = "./keystore";
+/// Written inside the keystore directory once it is encrypted
+pub const KEYSTORE_HEADER_FILE: &str = "keystore.json";
+/// PBKDF2 iterations for new keystores
+pub const DEFAULT_KDF_ITERATIONS: u32 = 600_000;
+
+const KDF_PBKDF2_SHA256: &str = "pbkdf2-hmac-sha256";
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+/// Plaintext of the header check value, decrypting it proves the passphrase is right
+const CHECK_PLAINTEXT: &[u8] = b"miden-faucet-keystore";
+
+/// Salt and KDF parameters of an encrypted keystore
+#[derive(Serialize, Deserialize)]
+struct KeystoreHeader {
+    kdf: String,
+    iterations: u32,
+    salt: String,
+    check: String,
+}
+
+impl KeystoreHeader {
+    fn path(dir: &Path) -> PathBuf {
+        dir.join(KEYSTORE_HEADER_FILE)
+    }
+
+    fn load(dir: &Path) -> Result<Option<Self>, String> {
+        let path = Self::path(dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read keystore header {}: {}", path.display(), err))?;
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|err| format!("Invalid keystore header {}: {}", path.display(), err))
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).expect("header is serializable");
+        write_private(&Self::path(dir), contents.as_bytes())
+    }
+}
+
+/// Passphrase from `KEYSTORE_PASSPHRASE`, or the contents of the file at `KEYSTORE_KEY_FILE`
+fn keystore_secret() -> Result<Option<Zeroizing<Vec<u8>>>, String> {
+    if let Ok(passphrase) = std::env::var("KEYSTORE_PASSPHRASE")
+        && !passphrase.is_empty()
+    {
+        return Ok(Some(Zeroizing::new(passphrase.into_bytes())));
+    }
+    match std::env::var("KEYSTORE_KEY_FILE") {
+        Ok(path) if !path.trim().is_empty() => {
+            let mut secret =
+                Zeroizing::new(std::fs::read(path.trim()).map_err(|err| {
+                    format!("Failed to read KEYSTORE_KEY_FILE {}: {}", path, err)
+                })?);
+            while secret.last().is_some_and(|byte| byte.is_ascii_whitespace()) {
+                secret.pop();
+            }
+            if secret.is_empty() {
+                return Err(format!("KEYSTORE_KEY_FILE {} is empty", path));
+            }
+            Ok(Some(secret))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// PBKDF2-HMAC-SHA256 of `secret`, the output is exactly the 32 byte cipher key
+fn derive_key(secret: &[u8], salt: &[u8], iterations: u32) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2_hmac::<Sha256>(secret, salt, iterations, key.as_mut());
+    key
+}
+
+/// Same file name `FilesystemKeyStore` uses for a public key
+fn key_file_name(pub_key: Word) -> String {
+    let mut hasher = DefaultHasher::new();
+    pub_key.to_hex().hash(&mut hasher);
+    hasher.finish().to_string()
+}
+
+/// Writes a file only the owner can read
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    // write next to the target and rename, so a crash never leaves a half written key
+    let tmp_path = path.with_extension("tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&tmp_path)
+        .map_err(|err| format!("Failed to write {}: {}", tmp_path.display(), err))?;
+    std::io::Write::write_all(&mut file, contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|err| format!("Failed to write {}: {}", tmp_path.display(), err))?;
+    std::fs::rename(&tmp_path, path)
+        .map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+}
+
+/// Keystore whose key files are encrypted, see the module docs
+pub struct EncryptedKeyStore {
+    dir: PathBuf,
+    cipher: XChaCha20Poly1305,
+    rng: Arc<RwLock<StdRng>>,
+}
+
+impl EncryptedKeyStore {
+    /// Derives the cipher key from `secret` and checks it against the header
+    fn open(dir: &Path, header: &KeystoreHeader, secret: &[u8]) -> Result<Self, String> {
+        if header.kdf != KDF_PBKDF2_SHA256 {
+            return Err(format!("Unsupported keystore kdf {}", header.kdf));
+        }
+        let salt = hex::decode(&header.salt).map_err(|err| format!("Invalid salt: {}", err))?;
+        let key = derive_key(secret, &salt, header.iterations);
+        let keystore = Self {
+            dir: dir.to_path_buf(),
+            cipher: XChaCha20Poly1305::new(key.as_ref().into()),
+            rng: Arc::new(RwLock::new(StdRng::from_os_rng())),
+        };
+        match keystore.decrypt(&header.check, KEYSTORE_HEADER_FILE) {
+            Ok(check) if check.as_slice() == CHECK_PLAINTEXT => Ok(keystore),
+            _ => Err("Wrong keystore passphrase or key file".to_string()),
+        }
+    }
+
+    /// Writes a new header for `dir`, the keystore is encrypted from then on
+    fn create(dir: &Path, secret: &[u8], iterations: u32) -> Result<Self, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| format!("Failed to create keystore {}: {}", dir.display(), err))?;
+        let mut salt = [0u8; 16];
+        rand::rng().fill_bytes(&mut salt);
+        let mut header = KeystoreHeader {
+            kdf: KDF_PBKDF2_SHA256.to_string(),
+            iterations,
+            salt: hex::encode(salt),
+            check: String::new(),
+        };
+        let key = derive_key(secret, &salt, header.iterations);
+        let keystore = Self {
+            dir: dir.to_path_buf(),
+            cipher: XChaCha20Poly1305::new(key.as_ref().into()),
+            rng: Arc::new(RwLock::new(StdRng::from_os_rng())),
+        };
+        header.check = keystore.encrypt(CHECK_PLAINTEXT, KEYSTORE_HEADER_FILE)?;
+        header.save(dir)?;
+        Ok(keystore)
+    }
+
+    /// Seals `plaintext`, binding it to `file_name` so key files cannot be swapped around
+    fn encrypt(&self, plaintext: &[u8], file_name: &str) -> Result<String, String> {
+        let mut nonce = [0u8; 24];
+        rand::rng().fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: plaintext,
+            aad: file_name.as_bytes(),
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .map_err(|_| "Failed to encrypt key".to_string())?;
+        Ok(format!(
+            "{}{}:{}",
+            ENCRYPTED_PREFIX,
+            hex::encode(nonce),
+            hex::encode(ciphertext)
+        ))
+    }
+
+    fn decrypt(&self, contents: &str, file_name: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+        let (nonce, ciphertext) = contents
+            .trim()
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|sealed| sealed.split_once(':'))
+            .ok_or_else(|| {
+                format!(
+                    "Key {} is not encrypted, run encrypt-keystore to finish the migration",
+                    file_name
+                )
+            })?;
+        let nonce = hex::decode(nonce).map_err(|err| format!("Invalid nonce: {}", err))?;
+        let ciphertext =
+            hex::decode(ciphertext).map_err(|err| format!("Invalid ciphertext: {}", err))?;
+        if nonce.len() != 24 {
+            return Err(format!("Invalid nonce length in key {}", file_name));
+        }
+        let payload = Payload {
+            msg: &ciphertext,
+            aad: file_name.as_bytes(),
+        };
+        self.cipher
+            .decrypt(XNonce::from_slice(&nonce), payload)
+            .map(Zeroizing::new)
+            .map_err(|_| format!("Failed to decrypt key {}", file_name))
+    }
+
+    fn add_key(&self, key: &AuthSecretKey) -> Result<(), String> {
+        let file_name = key_file_name(key.public_key().to_commitment().into());
+        let plaintext = Zeroizing::new(key.to_bytes());
+        let sealed = self.encrypt(&plaintext, &file_name)?;
+        write_private(&self.dir.join(&file_name), sealed.as_bytes())
+    }
+
+    fn get_key(&self, pub_key: Word) -> Result<Option<AuthSecretKey>, String> {
+        let file_name = key_file_name(pub_key);
+        let path = self.dir.join(&file_name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read key {}: {}", path.display(), err))?;
+        let plaintext = self.decrypt(&contents, &file_name)?;
+        AuthSecretKey::read_from_bytes(&plaintext)
+            .map(Some)
+            .map_err(|err| format!("Failed to decode key {}: {}", file_name, err))
+    }
+
+    /// Encrypts every plaintext key file left in the keystore, returns how many
+    fn encrypt_plaintext_keys(&self) -> Result<usize, String> {
+        let mut encrypted = 0;
+        for file_name in key_files(&self.dir)? {
+            let path = self.dir.join(&file_name);
+            let contents = Zeroizing::new(
+                std::fs::read_to_string(&path)
+                    .map_err(|err| format!("Failed to read key {}: {}", path.display(), err))?,
+            );
+            if contents.trim().starts_with(ENCRYPTED_PREFIX) {
+                continue;
+            }
+            let plaintext = Zeroizing::new(
+                hex::decode(contents.trim())
+                    .map_err(|err| format!("Failed to decode key {}: {}", file_name, err))?,
+            );
+            // make sure it is a key before sealing it
+            AuthSecretKey::read_from_bytes(&plaintext)
+                .map_err(|err| format!("Failed to decode key {}: {}", file_name, err))?;
+            let sealed = self.encrypt(&plaintext, &file_name)?;
+            write_private(&path, sealed.as_bytes())?;
+            encrypted += 1;
+        }
+        Ok(encrypted)
+    }
+}
+
+/// Names of the key files in `dir`, skipping the header and leftovers of interrupted writes
+fn key_files(dir: &Path) -> Result<Vec<String>, String> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("Failed to read keystore {}: {}", dir.display(), err))?;
+    let mut files = vec![];
+    for entry in entries {
+        let entry = entry.map_err(|err| format!("Failed to read keystore entry {}", err))?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if entry.path().is_file() && file_name != KEYSTORE_HEADER_FILE && !file_name.contains('.') {
+            files.push(file_name);
+        }
+    }
+    Ok(files)
+}
+
+/// Keystore used by the faucet clients, either miden's plaintext `FilesystemKeyStore` or an
+/// [`EncryptedKeyStore`]
+pub enum FaucetKeyStore {
+    Plaintext {
+        dir: PathBuf,
+        keystore: FilesystemKeyStore<StdRng>,
+    },
+    Encrypted(EncryptedKeyStore),
+}
+
+impl FaucetKeyStore {
+    /// Opens the keystore in `dir`, decrypting it with `KEYSTORE_PASSPHRASE` or
+    /// `KEYSTORE_KEY_FILE`.
+    ///
+    /// An empty keystore is encrypted as soon as a passphrase or key file is set. A plaintext
+    /// keystore is refused when one is set, until `encrypt-keystore` migrated it.
+    pub fn unlock(dir: &str) -> Result<Self, String> {
+        let dir = Path::new(dir);
+        match (KeystoreHeader::load(dir)?, keystore_secret()?) {
+            (Some(header), Some(secret)) => Ok(Self::Encrypted(EncryptedKeyStore::open(
+                dir, &header, &secret,
+            )?)),
+            (Some(_), None) => Err(format!(
+                "Keystore {} is encrypted, set KEYSTORE_PASSPHRASE or KEYSTORE_KEY_FILE",
+                dir.display()
+            )),
+            (None, Some(secret)) => {
+                if !key_files(dir)?.is_empty() {
+                    return Err(format!(
+                        "Keystore {} holds plaintext keys, run encrypt-keystore first",
+                        dir.display()
+                    ));
+                }
+                Ok(Self::Encrypted(EncryptedKeyStore::create(
+                    dir,
+                    &secret,
+                    DEFAULT_KDF_ITERATIONS,
+                )?))
+            }
+            (None, None) => {
+                eprintln!(
+                    "Keystore {} is not encrypted, set KEYSTORE_PASSPHRASE or KEYSTORE_KEY_FILE and run encrypt-keystore",
+                    dir.display()
+                );
+                FilesystemKeyStore::new(dir.into())
+                    .map(|keystore| Self::Plaintext {
+                        dir: dir.to_path_buf(),
+                        keystore,
+                    })
+                    .map_err(|err| format!("Failed to open keystore {}: {}", dir.display(), err))
+            }
+        }
+    }
+
+    /// Encrypts a plaintext keystore in place with `KEYSTORE_PASSPHRASE` or `KEYSTORE_KEY_FILE`,
+    /// returns how many keys were encrypted. Picks up where an interrupted migration stopped.
+    pub fn encrypt_in_place(dir: &str) -> Result<usize, String> {
+        let dir = Path::new(dir);
+        let secret = keystore_secret()?.ok_or(
+            "Set KEYSTORE_PASSPHRASE or KEYSTORE_KEY_FILE to the secret to encrypt the keystore with",
+        )?;
+        let keystore = match KeystoreHeader::load(dir)? {
+            Some(header) => EncryptedKeyStore::open(dir, &header, &secret)?,
+            None => EncryptedKeyStore::create(dir, &secret, DEFAULT_KDF_ITERATIONS)?,
+        };
+        keystore.encrypt_plaintext_keys()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Self::Encrypted(_))
+    }
+
+    pub fn add_key(&self, key: &AuthSecretKey) -> Result<(), String> {
+        match self {
+            Self::Plaintext { keystore, .. } => keystore
+                .add_key(key)
+                .map_err(|err| format!("Failed to store key {}", err)),
+            Self::Encrypted(keystore) => keystore.add_key(key),
+        }
+    }
+
+    pub fn get_key(&self, pub_key: Word) -> Result<Option<AuthSecretKey>, String> {
+        match self {
+            Self::Plaintext { keystore, .. } => keystore
+                .get_key(pub_key)
+                .map_err(|err| format!("Failed to read key {}", err)),
+            Self::Encrypted(keystore) => keystore.get_key(pub_key),
+        }
+    }
+
+    /// Deletes the key of `pub_key`, a missing key is not an error
+    pub fn remove_key(&self, pub_key: Word) -> Result<(), String> {
+        let dir = match self {
+            Self::Plaintext { dir, .. } => dir,
+            Self::Encrypted(keystore) => &keystore.dir,
+        };
+        let path = dir.join(key_file_name(pub_key));
+        match std::fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to remove key {}: {}", path.display(), err))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Lets the client builder fall back to a plain `FilesystemKeyStore` in [`KEYSTORE_DIR`]
+impl From<FilesystemKeyStore<StdRng>> for FaucetKeyStore {
+    fn from(keystore: FilesystemKeyStore<StdRng>) -> Self {
+        Self::Plaintext {
+            dir: PathBuf::from(KEYSTORE_DIR),
+            keystore,
+        }
+    }
+}
+
+impl TransactionAuthenticator for FaucetKeyStore {
+    async fn get_signature(
+        &self,
+        pub_key: PublicKeyCommitment,
+        signing_inputs: &SigningInputs,
+    ) -> Result<Signature, AuthenticationError> {
+        let keystore = match self {
+            Self::Plaintext { keystore, .. } => {
+                return keystore.get_signature(pub_key, signing_inputs).await;
+            }
+            Self::Encrypted(keystore) => keystore,
+        };
+        let message = signing_inputs.to_commitment();
+        let secret_key = keystore
+            .get_key(pub_key.into())
+            .map_err(AuthenticationError::other)?
+            .ok_or_else(|| AuthenticationError::other("missing secret key"))?;
+        let signature = match secret_key {
+            AuthSecretKey::RpoFalcon512(key) => {
+                let mut rng = keystore.rng.write().expect("poisoned lock");
+                Signature::RpoFalcon512(key.sign_with_rng(message, &mut *rng))
+            }
+            other => other.sign(message),
+        };
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use miden_client::crypto::rpo_falcon512::SecretKey;
+
+    use super::*;
+
+    /// Keeps the tests fast, the derivation itself is checked against a known vector
+    const TEST_KDF_ITERATIONS: u32 = 1_000;
+
+    /// Fresh keystore directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("keystore-test-{}", rand::rng().next_u64()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn derive_key_is_pbkdf2_hmac_sha256() {
+        let key = derive_key(b"password", b"salt", 4096);
+        assert_eq!(
+            hex::encode(*key),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn encrypted_key_round_trips() {
+        let dir = TempDir::new();
+        let keystore =
+            EncryptedKeyStore::create(&dir.0, b"correct horse", TEST_KDF_ITERATIONS).unwrap();
+        let key = AuthSecretKey::RpoFalcon512(SecretKey::with_rng(&mut StdRng::from_os_rng()));
+        let pub_key: Word = key.public_key().to_commitment().into();
+        keystore.add_key(&key).unwrap();
+
+        let contents = std::fs::read_to_string(dir.0.join(key_file_name(pub_key))).unwrap();
+        assert!(contents.starts_with(ENCRYPTED_PREFIX));
+
+        let header = KeystoreHeader::load(&dir.0).unwrap().unwrap();
+        let reopened = EncryptedKeyStore::open(&dir.0, &header, b"correct horse").unwrap();
+        let decrypted = reopened.get_key(pub_key).unwrap().unwrap();
+        assert_eq!(decrypted.to_bytes(), key.to_bytes());
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let dir = TempDir::new();
+        EncryptedKeyStore::create(&dir.0, b"correct horse", TEST_KDF_ITERATIONS).unwrap();
+
+        let header = KeystoreHeader::load(&dir.0).unwrap().unwrap();
+        let err = EncryptedKeyStore::open(&dir.0, &header, b"battery staple")
+            .err()
+            .unwrap();
+        assert_eq!(err, "Wrong keystore passphrase or key file");
+    }
+}
@@ -1,5 +1,15 @@
+pub mod amount;
 pub mod faucet;
+pub mod key_rotation;
+pub mod keystore;
+pub mod mint_error;
+pub mod mint_queue;
+pub mod network;
+pub mod note_screener;
+pub mod pow;
+pub mod prover;
+pub mod rate_limit;
+pub mod reclaim;
 pub mod server;
 pub mod tx_worker;
 pub mod utils;
-pub mod note_screener;
\ No newline at end of file
@@ -1,9 +1,10 @@
-use std::env;
-
-use miden_client::rpc::Endpoint;
+use std::{env, sync::Arc};
 
 use miden_faucet_server::{
-    faucet,
+    faucet::{self, FaucetConfig, FaucetParams, configured_faucets, faucet_config_path},
+    key_rotation,
+    keystore::{FaucetKeyStore, KEYSTORE_DIR},
+    network::NetworkConfig,
     server::{self},
 };
 
@@ -16,21 +17,106 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             server::start_server().await?;
         }
         "create-faucet" => {
-            let network = env::args().nth(2).unwrap_or_else(|| "testnet".to_string());
-            let endpoint = match network.as_str() {
-                "testnet" => Endpoint::testnet(),
-                "localnet" => Endpoint::localhost(),
-                "devnet" => Endpoint::devnet(),
-                _ => {
-                    eprintln!("Unknown network: {}. Use 'testnet' or 'mainnet'.", network);
+            // create-faucet [network] [--symbol S] [--decimals N] [--max-supply TOKENS]
+            //               [--storage-mode public|network] [--force]
+            let mut args: Vec<String> = env::args().skip(2).collect();
+            // the network argument takes precedence over MIDEN_NETWORK
+            let network_arg = match args.first() {
+                Some(arg) if !arg.starts_with("--") => Some(args.remove(0)),
+                _ => None,
+            };
+            let force = args.iter().any(|arg| arg == "--force");
+            args.retain(|arg| arg != "--force");
+
+            let network = match NetworkConfig::from_env_or(network_arg.as_deref()) {
+                Ok(network) => network,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return Ok(());
+                }
+            };
+            let params = match FaucetParams::from_args(&args) {
+                Ok(params) => params,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return Ok(());
+                }
+            };
+            let symbol = params.symbol.to_string()?;
+            if let Some(existing) = FaucetConfig::load_all()?
+                .into_iter()
+                .find(|faucet| faucet.symbol.eq_ignore_ascii_case(&symbol))
+                && !force
+            {
+                eprintln!(
+                    "{} already has a {} faucet {}, pass --force to replace it",
+                    faucet_config_path(),
+                    symbol,
+                    existing.faucet_id
+                );
+                return Ok(());
+            }
+            let keystore = match FaucetKeyStore::unlock(KEYSTORE_DIR) {
+                Ok(keystore) => Arc::new(keystore),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return Ok(());
+                }
+            };
+            faucet::create_new_faucet(&network, &params, keystore).await?
+        }
+        "encrypt-keystore" => {
+            // encrypts the plaintext keys left by older versions with KEYSTORE_PASSPHRASE or
+            // KEYSTORE_KEY_FILE, the binaries refuse to start on a half migrated keystore
+            match FaucetKeyStore::encrypt_in_place(KEYSTORE_DIR) {
+                Ok(encrypted) => println!("Encrypted {} keys in {}", encrypted, KEYSTORE_DIR),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        "rotate-faucet-key" => {
+            // rotate-faucet-key [faucet id or symbol], defaults to the default faucet. Stop the
+            // mint server first, its mint transactions would race the rotation for the nonce
+            let faucets = match configured_faucets() {
+                Ok(faucets) => faucets,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return Ok(());
+                }
+            };
+            let faucet = match env::args().nth(2) {
+                Some(key) => match faucets.iter().find(|faucet| faucet.matches(&key)) {
+                    Some(faucet) => faucet,
+                    None => {
+                        eprintln!("Unknown faucet {}", key);
+                        return Ok(());
+                    }
+                },
+                None => &faucets[0],
+            };
+            // the faucet lives on the network it was created on
+            let network_name = Some(faucet.network.as_str()).filter(|name| !name.is_empty());
+            let network = match NetworkConfig::from_env_or(network_name) {
+                Ok(network) => network,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return Ok(());
+                }
+            };
+            let keystore = match FaucetKeyStore::unlock(KEYSTORE_DIR) {
+                Ok(keystore) => Arc::new(keystore),
+                Err(err) => {
+                    eprintln!("{}", err);
                     return Ok(());
                 }
             };
-            faucet::create_new_faucet(endpoint).await?
+            key_rotation::rotate_faucet_key(network, faucet, keystore).await?
         }
         _ => {
             eprintln!("Unknown command: {}", command);
-            eprintln!("Usage: {} <start-server>", env::args().next().unwrap());
+            eprintln!(
+                "Usage: {} <start-server | create-faucet [network] [--symbol S] [--decimals N] [--max-supply TOKENS] [--storage-mode public|network] [--force] | encrypt-keystore | rotate-faucet-key [faucet]>",
+                env::args().next().unwrap()
+            );
         }
     };
 
@@ -0,0 +1,137 @@
+//! Errors of the mint api and queue processor, each with an http status and a stable code that
+//! clients can match on.
+use std::fmt;
+
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MintError {
+    /// the request body or a parameter is malformed
+    InvalidRequest(String),
+    /// the address does not decode or belongs to another network
+    InvalidAddress(String),
+    /// the address decodes but does not point to an account
+    UnsupportedAddressKind(String),
+    /// the amount does not parse, has more decimals than the faucet or does not fit in base units
+    InvalidAmount(String),
+    /// the amount is zero, above the per request maximum or not representable
+    AmountOutOfRange(String),
+    /// missing, expired or wrong proof of work
+    ProofOfWork(String),
+    RateLimited {
+        retry_at: u64,
+        retry_after: u64,
+    },
+    NotFound(String),
+    /// the mint transaction could not be executed against the faucet account
+    Execution(String),
+    /// neither prover produced a proof
+    Proving(String),
+    /// the node rejected or discarded the mint transaction
+    SubmissionRejected(String),
+    /// the transaction went through but the private note could not be delivered
+    Transport(String),
+    /// the request failed too many times and was given up on
+    AttemptsExhausted,
+    Internal(String),
+}
+
+impl MintError {
+    /// Machine readable error code, stored with failed requests and returned by the api
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::InvalidAddress(_) => "invalid_address",
+            Self::UnsupportedAddressKind(_) => "unsupported_address_kind",
+            Self::InvalidAmount(_) => "invalid_amount",
+            Self::AmountOutOfRange(_) => "amount_out_of_range",
+            Self::ProofOfWork(_) => "invalid_proof_of_work",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::NotFound(_) => "not_found",
+            Self::Execution(_) => "execution_failed",
+            Self::Proving(_) => "proving_failed",
+            Self::SubmissionRejected(_) => "submission_rejected",
+            Self::Transport(_) => "transport_failed",
+            Self::AttemptsExhausted => "attempts_exhausted",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidRequest(_)
+            | Self::InvalidAddress(_)
+            | Self::UnsupportedAddressKind(_)
+            | Self::InvalidAmount(_) => StatusCode::BAD_REQUEST,
+            Self::AmountOutOfRange(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::ProofOfWork(_) => StatusCode::FORBIDDEN,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Proving(_) | Self::AttemptsExhausted => StatusCode::SERVICE_UNAVAILABLE,
+            Self::SubmissionRejected(_) => StatusCode::BAD_GATEWAY,
+            Self::Transport(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::Execution(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for MintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRequest(msg)
+            | Self::InvalidAddress(msg)
+            | Self::UnsupportedAddressKind(msg)
+            | Self::InvalidAmount(msg)
+            | Self::AmountOutOfRange(msg)
+            | Self::ProofOfWork(msg)
+            | Self::NotFound(msg)
+            | Self::Execution(msg)
+            | Self::Proving(msg)
+            | Self::SubmissionRejected(msg)
+            | Self::Transport(msg)
+            | Self::Internal(msg) => write!(f, "{}", msg),
+            Self::RateLimited {
+                retry_at,
+                retry_after,
+            } => write!(
+                f,
+                "Rate limited, retry after {} seconds (at unix time {})",
+                retry_after, retry_at
+            ),
+            Self::AttemptsExhausted => write!(f, "Gave up after repeated attempts"),
+        }
+    }
+}
+
+impl std::error::Error for MintError {}
+
+/// Body of every mint api error, `{"error": "...", "code": "..."}`
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+    code: &'static str,
+}
+
+impl IntoResponse for MintError {
+    fn into_response(self) -> Response {
+        let mut response = (
+            self.status(),
+            Json(ErrorBody {
+                error: self.to_string(),
+                code: self.code(),
+            }),
+        )
+            .into_response();
+        if let Self::RateLimited { retry_after, .. } = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
@@ -0,0 +1,780 @@
+//! Durable mint queue, every mint request is journaled in the `MINT_REQUESTS` table of
+//! [`crate::utils::MINT_DB`] and moves through
+//! `queued -> batching -> proving -> submitted -> committed`, or ends up `failed`.
+use std::time::Duration;
+
+use miden_client::note::NoteType;
+use rand::RngCore;
+use rusqlite::{Connection, OptionalExtension, Row, TransactionBehavior};
+use serde::Serialize;
+
+use crate::{
+    mint_error::MintError,
+    utils::{env_or, now_secs},
+};
+
+/// Default maximum number of notes minted in a single transaction
+pub const DEFAULT_MINT_BATCH_SIZE: usize = 32;
+/// Default time the processor waits for more requests before minting a partial batch
+pub const DEFAULT_MINT_FLUSH_INTERVAL_MS: u64 = 5_000;
+
+/// How the queue processor groups requests into transactions
+#[derive(Debug, Clone, Copy)]
+pub struct MintBatchConfig {
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+}
+
+impl MintBatchConfig {
+    /// Reads `MINT_BATCH_SIZE` and `MINT_FLUSH_INTERVAL_MS`
+    pub fn from_env() -> Self {
+        let batch_size = env_or("MINT_BATCH_SIZE", DEFAULT_MINT_BATCH_SIZE).max(1);
+        let flush_interval_ms = env_or("MINT_FLUSH_INTERVAL_MS", DEFAULT_MINT_FLUSH_INTERVAL_MS);
+        Self {
+            batch_size,
+            flush_interval: Duration::from_millis(flush_interval_ms),
+        }
+    }
+}
+
+/// Which note types callers can ask for, read from `MINT_NOTE_TYPE` (the default) and
+/// `MINT_ALLOWED_NOTE_TYPES` (comma separated)
+#[derive(Debug, Clone)]
+pub struct MintNoteTypeConfig {
+    pub default: NoteType,
+    pub allowed: Vec<NoteType>,
+}
+
+fn parse_note_type(note_type: &str) -> Result<NoteType, String> {
+    match note_type.trim().to_lowercase().as_str() {
+        "private" => Ok(NoteType::Private),
+        "public" => Ok(NoteType::Public),
+        other => Err(format!(
+            "Unsupported note type {}, use private or public",
+            other
+        )),
+    }
+}
+
+impl MintNoteTypeConfig {
+    /// Without a note transport private notes cannot be delivered, so they default to public and
+    /// asking for private ones in the environment is an error.
+    pub fn from_env(note_transport: bool) -> Result<Self, String> {
+        let default = match std::env::var("MINT_NOTE_TYPE") {
+            Ok(note_type) => parse_note_type(&note_type)?,
+            Err(_) if note_transport => NoteType::Private,
+            Err(_) => NoteType::Public,
+        };
+        let allowed = match std::env::var("MINT_ALLOWED_NOTE_TYPES") {
+            Ok(note_types) => note_types
+                .split(',')
+                .filter(|note_type| !note_type.trim().is_empty())
+                .map(parse_note_type)
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(_) if note_transport => vec![NoteType::Private, NoteType::Public],
+            Err(_) => vec![NoteType::Public],
+        };
+        if !note_transport && (default == NoteType::Private || allowed.contains(&NoteType::Private))
+        {
+            return Err(
+                "Private notes are allowed but the note transport is disabled, set MINT_NOTE_TYPE and MINT_ALLOWED_NOTE_TYPES to public".to_string(),
+            );
+        }
+        if !allowed.contains(&default) {
+            return Err("MINT_NOTE_TYPE is not in MINT_ALLOWED_NOTE_TYPES".to_string());
+        }
+        Ok(Self { default, allowed })
+    }
+
+    /// Resolves the requested note type, falling back to the default when none is given
+    pub fn resolve(&self, note_type: Option<&str>) -> Result<NoteType, String> {
+        let Some(note_type) = note_type else {
+            return Ok(self.default);
+        };
+        let note_type = parse_note_type(note_type)?;
+        if !self.allowed.contains(&note_type) {
+            return Err(format!("Note type {} is not allowed", note_type));
+        }
+        Ok(note_type)
+    }
+}
+
+/// A request that was picked up this many times without finishing is failed instead of retried
+pub const MAX_MINT_ATTEMPTS: u32 = 3;
+
+pub const STATUS_QUEUED: &str = "queued";
+pub const STATUS_BATCHING: &str = "batching";
+/// executed, its tx id is journaled before it is proven and submitted
+pub const STATUS_PROVING: &str = "proving";
+pub const STATUS_SUBMITTED: &str = "submitted";
+pub const STATUS_COMMITTED: &str = "committed";
+pub const STATUS_FAILED: &str = "failed";
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MintRequestRecord {
+    pub request_id: String,
+    /// hex id of the faucet minting the request
+    pub faucet_id: String,
+    pub address: String,
+    pub amount: u64,
+    pub note_type: String,
+    pub status: String,
+    pub tx_id: Option<String>,
+    pub note_id: Option<String>,
+    /// nullifier of the note, lets the indexer link a private note to the transaction that
+    /// consumed it
+    pub nullifier: Option<String>,
+    pub account_id: Option<String>,
+    /// prover that proved the mint transaction, `remote` or `local`
+    pub prover: Option<String>,
+    pub error: Option<String>,
+    /// machine readable code of `error`, see [`MintError::code`]
+    pub error_code: Option<String>,
+    /// why the private note of a submitted request could not be delivered yet, cleared once
+    /// a retry delivers it
+    pub delivery_error: Option<String>,
+    pub attempts: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl MintRequestRecord {
+    fn from_sql_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            request_id: row.get("request_id")?,
+            faucet_id: row.get("faucet_id")?,
+            address: row.get("address")?,
+            amount: row.get("amount")?,
+            note_type: row.get("note_type")?,
+            status: row.get("status")?,
+            tx_id: row.get("tx_id")?,
+            note_id: row.get("note_id")?,
+            nullifier: row.get("nullifier")?,
+            account_id: row.get("account_id")?,
+            prover: row.get("prover")?,
+            error: row.get("error")?,
+            error_code: row.get("error_code")?,
+            delivery_error: row.get("delivery_error")?,
+            attempts: row.get("attempts")?,
+            created_at: row.get("created_at")?,
+            updated_at: row.get("updated_at")?,
+        })
+    }
+}
+
+pub fn create_mint_requests_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS MINT_REQUESTS (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            request_id TEXT NOT NULL UNIQUE,
+            faucet_id TEXT NOT NULL,
+            address TEXT NOT NULL,
+            amount INTEGER NOT NULL,
+            note_type TEXT CHECK(note_type IN ('private', 'public')) NOT NULL,
+            status TEXT CHECK(status IN ('queued', 'batching', 'proving', 'submitted', 'committed', 'failed')) NOT NULL,
+            tx_id TEXT NULL DEFAULT NULL,
+            note_id TEXT NULL DEFAULT NULL,
+            nullifier TEXT NULL DEFAULT NULL,
+            account_id TEXT NULL DEFAULT NULL,
+            prover TEXT NULL DEFAULT NULL,
+            error TEXT NULL DEFAULT NULL,
+            error_code TEXT NULL DEFAULT NULL,
+            delivery_error TEXT NULL DEFAULT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS MINT_REQUESTS_STATUS ON MINT_REQUESTS (status);
+        ",
+    )
+}
+
+/// Journals a new mint request and returns its request id
+pub fn enqueue_mint_request(
+    conn: &Connection,
+    faucet_id: &str,
+    address: &str,
+    amount: u64,
+    note_type: NoteType,
+) -> Result<String, String> {
+    let mut request_id = [0u8; 16];
+    rand::rng().fill_bytes(&mut request_id);
+    let request_id = hex::encode(request_id);
+    let now = now_secs();
+    conn.execute(
+        "INSERT INTO MINT_REQUESTS (request_id, faucet_id, address, amount, note_type, status, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)",
+        (&request_id, faucet_id, address, amount, note_type.to_string(), STATUS_QUEUED, now),
+    )
+    .map_err(|err| format!("Failed to enqueue mint request {}", err))?;
+    Ok(request_id)
+}
+
+pub fn get_mint_request(
+    conn: &Connection,
+    request_id: &str,
+) -> Result<Option<MintRequestRecord>, String> {
+    conn.query_row(
+        "SELECT * FROM MINT_REQUESTS WHERE request_id = ?1",
+        (request_id,),
+        MintRequestRecord::from_sql_row,
+    )
+    .optional()
+    .map_err(|err| format!("Failed to get mint request {}", err))
+}
+
+/// Amount (base units) the mint server put into `note_id` and the note's nullifier, `None` for
+/// notes it did not create
+pub fn get_minted_note(
+    conn: &Connection,
+    note_id: &str,
+) -> Result<Option<(u64, Option<String>)>, String> {
+    conn.query_row(
+        "SELECT amount, nullifier FROM MINT_REQUESTS WHERE note_id = ?1",
+        (note_id,),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|err| format!("Failed to get mint request {}", err))
+}
+
+pub fn count_queued(conn: &Connection) -> Result<usize, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM MINT_REQUESTS WHERE status = ?1",
+        (STATUS_QUEUED,),
+        |row| row.get(0),
+    )
+    .map_err(|err| format!("Failed to count queued mint requests {}", err))
+}
+
+/// Base units minted by `faucet_id` in requests submitted or committed within the last
+/// `window_secs`
+pub fn minted_in_last_secs(
+    conn: &Connection,
+    faucet_id: &str,
+    window_secs: u64,
+) -> Result<u64, String> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(amount), 0) FROM MINT_REQUESTS WHERE faucet_id = ?1 AND status IN (?2, ?3) AND updated_at >= ?4",
+        (
+            faucet_id,
+            STATUS_SUBMITTED,
+            STATUS_COMMITTED,
+            now_secs().saturating_sub(window_secs),
+        ),
+        |row| row.get(0),
+    )
+    .map_err(|err| format!("Failed to sum minted amount {}", err))
+}
+
+/// 1-based position of a queued request in the queue, `None` once it left the queue
+pub fn get_queue_position(conn: &Connection, request_id: &str) -> Result<Option<usize>, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM MINT_REQUESTS
+         WHERE status = ?1 AND id <= (SELECT id FROM MINT_REQUESTS WHERE request_id = ?2 AND status = ?1)",
+        (STATUS_QUEUED, request_id),
+        |row| row.get::<usize, usize>(0),
+    )
+    .map(|position| (position > 0).then_some(position))
+    .map_err(|err| format!("Failed to get queue position {}", err))
+}
+
+/// Moves up to `limit` queued requests of a single faucet to `batching` and returns them, oldest
+/// first. The faucet is the one of the oldest queued request, so every faucet gets its turn.
+///
+/// Requests that already used up [`MAX_MINT_ATTEMPTS`] are failed instead, their ids are returned
+/// alongside the batch.
+pub fn claim_queued(
+    conn: &mut Connection,
+    limit: usize,
+) -> Result<(Vec<MintRequestRecord>, Vec<String>), String> {
+    let now = now_secs();
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|err| format!("Failed to start claim transaction {}", err))?;
+    let exhausted = {
+        let mut stmt = tx
+            .prepare("SELECT request_id FROM MINT_REQUESTS WHERE status = ?1 AND attempts >= ?2")
+            .map_err(|err| format!("Failed to get exhausted mint requests {}", err))?;
+        let rows = stmt
+            .query_map((STATUS_QUEUED, MAX_MINT_ATTEMPTS), |row| row.get(0))
+            .map_err(|err| format!("Failed to get exhausted mint requests {}", err))?;
+        let mut exhausted = vec![];
+        for row in rows {
+            exhausted.push(row.map_err(|err| format!("Error getting mint request {}", err))?);
+        }
+        exhausted
+    };
+    tx.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, error = ?2, error_code = ?3, updated_at = ?4 WHERE status = ?5 AND attempts >= ?6",
+        (
+            STATUS_FAILED,
+            MintError::AttemptsExhausted.to_string(),
+            MintError::AttemptsExhausted.code(),
+            now,
+            STATUS_QUEUED,
+            MAX_MINT_ATTEMPTS,
+        ),
+    )
+    .map_err(|err| format!("Failed to fail exhausted mint requests {}", err))?;
+    let faucet_id: Option<String> = tx
+        .query_row(
+            "SELECT faucet_id FROM MINT_REQUESTS WHERE status = ?1 ORDER BY id ASC LIMIT 1",
+            (STATUS_QUEUED,),
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|err| format!("Failed to get queued mint requests {}", err))?;
+    let records = {
+        let mut stmt = tx
+            .prepare("SELECT * FROM MINT_REQUESTS WHERE status = ?1 AND faucet_id = ?2 ORDER BY id ASC LIMIT ?3")
+            .map_err(|err| format!("Failed to get queued mint requests {}", err))?;
+        let rows = stmt
+            .query_map(
+                (STATUS_QUEUED, &faucet_id, limit),
+                MintRequestRecord::from_sql_row,
+            )
+            .map_err(|err| format!("Failed to get queued mint requests {}", err))?;
+        let mut records = vec![];
+        for row in rows {
+            records.push(row.map_err(|err| format!("Error getting mint request {}", err))?);
+        }
+        records
+    };
+    tx.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, attempts = attempts + 1, updated_at = ?2
+         WHERE id IN (SELECT id FROM MINT_REQUESTS WHERE status = ?3 AND faucet_id = ?4 ORDER BY id ASC LIMIT ?5)",
+        (STATUS_BATCHING, now, STATUS_QUEUED, &faucet_id, limit),
+    )
+    .map_err(|err| format!("Failed to claim mint requests {}", err))?;
+    tx.commit()
+        .map_err(|err| format!("Failed to commit claim {}", err))?;
+    Ok((records, exhausted))
+}
+
+/// Puts requests left in `batching` by a previous run back in the queue, returns how many.
+///
+/// Requests left in `proving` are not touched, their transaction may have reached the node and
+/// has to be looked up before they can be retried, see [`requeue_tx`].
+pub fn requeue_interrupted(conn: &Connection) -> Result<usize, String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, updated_at = ?2 WHERE status = ?3",
+        (STATUS_QUEUED, now_secs(), STATUS_BATCHING),
+    )
+    .map_err(|err| format!("Failed to requeue mint requests {}", err))
+}
+
+/// Requests whose transaction was executed but not known to be submitted, left by a run that
+/// stopped while proving
+pub fn get_proving_requests(conn: &Connection) -> Result<Vec<MintRequestRecord>, String> {
+    let mut stmt = conn
+        .prepare("SELECT * FROM MINT_REQUESTS WHERE status = ?1 ORDER BY id ASC")
+        .map_err(|err| format!("Failed to get proving mint requests {}", err))?;
+    let rows = stmt
+        .query_map((STATUS_PROVING,), MintRequestRecord::from_sql_row)
+        .map_err(|err| format!("Failed to get proving mint requests {}", err))?;
+    let mut res = vec![];
+    for row in rows {
+        res.push(row.map_err(|err| format!("Error getting mint request {}", err))?);
+    }
+    Ok(res)
+}
+
+/// Puts the requests of an interrupted transaction that never reached the chain back in the
+/// queue, the attempt still counts
+pub fn requeue_tx(conn: &Connection, tx_id: &str) -> Result<usize, String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, tx_id = NULL, note_id = NULL, nullifier = NULL, account_id = NULL, updated_at = ?2 WHERE tx_id = ?3 AND status = ?4",
+        (STATUS_QUEUED, now_secs(), tx_id, STATUS_PROVING),
+    )
+    .map_err(|err| format!("Failed to requeue mint requests {}", err))
+}
+
+/// Puts claimed requests back in the queue without counting the attempt
+pub fn requeue_requests(conn: &Connection, request_ids: &[&str]) -> Result<(), String> {
+    let now = now_secs();
+    for request_id in request_ids {
+        conn.execute(
+            "UPDATE MINT_REQUESTS SET status = ?1, attempts = MAX(attempts - 1, 0), updated_at = ?2 WHERE request_id = ?3 AND status = ?4",
+            (STATUS_QUEUED, now, request_id, STATUS_BATCHING),
+        )
+        .map_err(|err| format!("Failed to requeue mint request {}", err))?;
+    }
+    Ok(())
+}
+
+/// Journals the executed mint transaction of a request before it is proven and submitted
+pub fn mark_proving(
+    conn: &Connection,
+    request_id: &str,
+    tx_id: &str,
+    note_id: &str,
+    nullifier: &str,
+    account_id: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, tx_id = ?2, note_id = ?3, nullifier = ?4, account_id = ?5, updated_at = ?6 WHERE request_id = ?7",
+        (STATUS_PROVING, tx_id, note_id, nullifier, account_id, now_secs(), request_id),
+    )
+    .map_err(|err| format!("Failed to update mint request {}", err))?;
+    Ok(())
+}
+
+/// Moves the requests of an interrupted transaction the node accepted to `submitted`
+pub fn mark_tx_submitted(conn: &Connection, tx_id: &str) -> Result<(), String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, updated_at = ?2 WHERE tx_id = ?3 AND status = ?4",
+        (STATUS_SUBMITTED, now_secs(), tx_id, STATUS_PROVING),
+    )
+    .map_err(|err| format!("Failed to update mint request {}", err))?;
+    Ok(())
+}
+
+pub fn mark_submitted(
+    conn: &Connection,
+    request_id: &str,
+    tx_id: &str,
+    note_id: &str,
+    account_id: &str,
+    prover: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, tx_id = ?2, note_id = ?3, account_id = ?4, prover = ?5, error = NULL, error_code = NULL, updated_at = ?6 WHERE request_id = ?7",
+        (STATUS_SUBMITTED, tx_id, note_id, account_id, prover, now_secs(), request_id),
+    )
+    .map_err(|err| format!("Failed to update mint request {}", err))?;
+    Ok(())
+}
+
+pub fn mark_failed(conn: &Connection, request_id: &str, error: &MintError) -> Result<(), String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, error = ?2, error_code = ?3, updated_at = ?4 WHERE request_id = ?5",
+        (STATUS_FAILED, error.to_string(), error.code(), now_secs(), request_id),
+    )
+    .map_err(|err| format!("Failed to update mint request {}", err))?;
+    Ok(())
+}
+
+/// Keeps the request submitted, its tokens are minted, and records why its private note could
+/// not be delivered so the delivery is retried, see [`get_undelivered_requests`]
+pub fn mark_delivery_failed(
+    conn: &Connection,
+    request_id: &str,
+    error: &MintError,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET delivery_error = ?1, updated_at = ?2 WHERE request_id = ?3",
+        (error.to_string(), now_secs(), request_id),
+    )
+    .map_err(|err| format!("Failed to update mint request {}", err))?;
+    Ok(())
+}
+
+pub fn mark_delivered(conn: &Connection, request_id: &str) -> Result<(), String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET delivery_error = NULL, updated_at = ?1 WHERE request_id = ?2",
+        (now_secs(), request_id),
+    )
+    .map_err(|err| format!("Failed to update mint request {}", err))?;
+    Ok(())
+}
+
+/// Submitted or committed requests whose private note still has to be delivered
+pub fn get_undelivered_requests(conn: &Connection) -> Result<Vec<MintRequestRecord>, String> {
+    let mut stmt = conn
+        .prepare("SELECT * FROM MINT_REQUESTS WHERE delivery_error IS NOT NULL AND status IN (?1, ?2) ORDER BY id ASC")
+        .map_err(|err| format!("Failed to get undelivered mint requests {}", err))?;
+    let rows = stmt
+        .query_map(
+            (STATUS_SUBMITTED, STATUS_COMMITTED),
+            MintRequestRecord::from_sql_row,
+        )
+        .map_err(|err| format!("Failed to get undelivered mint requests {}", err))?;
+    let mut res = vec![];
+    for row in rows {
+        res.push(row.map_err(|err| format!("Error getting mint request {}", err))?);
+    }
+    Ok(res)
+}
+
+/// Transaction ids of submitted requests that are not known to be committed yet
+pub fn get_submitted_tx_ids(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT tx_id FROM MINT_REQUESTS WHERE status = ?1 AND tx_id IS NOT NULL")
+        .map_err(|err| format!("Failed to get submitted mint requests {}", err))?;
+    let rows = stmt
+        .query_map((STATUS_SUBMITTED,), |row| row.get(0))
+        .map_err(|err| format!("Failed to get submitted mint requests {}", err))?;
+    let mut res = vec![];
+    for row in rows {
+        res.push(row.map_err(|err| format!("Error getting submitted mint request {}", err))?);
+    }
+    Ok(res)
+}
+
+/// Request ids of the requests in `status` that were minted by `tx_id`
+pub fn get_request_ids_for_tx(
+    conn: &Connection,
+    tx_id: &str,
+    status: &str,
+) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT request_id FROM MINT_REQUESTS WHERE tx_id = ?1 AND status = ?2")
+        .map_err(|err| format!("Failed to get mint requests {}", err))?;
+    let rows = stmt
+        .query_map((tx_id, status), |row| row.get(0))
+        .map_err(|err| format!("Failed to get mint requests {}", err))?;
+    let mut res = vec![];
+    for row in rows {
+        res.push(row.map_err(|err| format!("Error getting mint request {}", err))?);
+    }
+    Ok(res)
+}
+
+pub fn mark_tx_committed(conn: &Connection, tx_id: &str) -> Result<(), String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, updated_at = ?2 WHERE tx_id = ?3 AND status = ?4",
+        (STATUS_COMMITTED, now_secs(), tx_id, STATUS_SUBMITTED),
+    )
+    .map_err(|err| format!("Failed to update mint request {}", err))?;
+    Ok(())
+}
+
+pub fn mark_tx_failed(conn: &Connection, tx_id: &str, error: &MintError) -> Result<(), String> {
+    conn.execute(
+        "UPDATE MINT_REQUESTS SET status = ?1, error = ?2, error_code = ?3, updated_at = ?4 WHERE tx_id = ?5 AND status = ?6",
+        (STATUS_FAILED, error.to_string(), error.code(), now_secs(), tx_id, STATUS_SUBMITTED),
+    )
+    .map_err(|err| format!("Failed to update mint request {}", err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAUCET: &str = "0xcda5ce1aab2c6a2011bff4904e3b8d";
+    const OTHER_FAUCET: &str = "0x1862a0efd4027a2036dd9aaac6df74";
+
+    fn queue() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        create_mint_requests_table(&conn).unwrap();
+        conn
+    }
+
+    fn enqueue(conn: &Connection, faucet_id: &str) -> String {
+        enqueue_mint_request(conn, faucet_id, "mtst1address", 100, NoteType::Private).unwrap()
+    }
+
+    fn request(conn: &Connection, request_id: &str) -> MintRequestRecord {
+        get_mint_request(conn, request_id).unwrap().unwrap()
+    }
+
+    fn ids(records: &[MintRequestRecord]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| record.request_id.as_str())
+            .collect()
+    }
+
+    /// Journals `request_id` as minted in `tx_id`, the way the processor does before proving
+    fn prove(conn: &Connection, request_id: &str, tx_id: &str) {
+        let note_id = format!("0xnote{}", request_id);
+        let nullifier = format!("0xnullifier{}", request_id);
+        mark_proving(
+            conn,
+            request_id,
+            tx_id,
+            &note_id,
+            &nullifier,
+            "mtst1account",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn claims_one_faucet_per_batch_oldest_first() {
+        let mut conn = queue();
+        let first = enqueue(&conn, FAUCET);
+        let other = enqueue(&conn, OTHER_FAUCET);
+        let second = enqueue(&conn, FAUCET);
+
+        let (batch, exhausted) = claim_queued(&mut conn, 10).unwrap();
+        assert_eq!(ids(&batch), [first.as_str(), second.as_str()]);
+        assert!(exhausted.is_empty());
+        for request_id in [&first, &second] {
+            let request = request(&conn, request_id);
+            assert_eq!(request.status, STATUS_BATCHING);
+            assert_eq!(request.attempts, 1);
+        }
+        assert_eq!(get_queue_position(&conn, &other), Ok(Some(1)));
+
+        let (batch, _) = claim_queued(&mut conn, 10).unwrap();
+        assert_eq!(ids(&batch), [other.as_str()]);
+        assert!(claim_queued(&mut conn, 10).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn claims_at_most_the_limit() {
+        let mut conn = queue();
+        let requests: Vec<String> = (0..3).map(|_| enqueue(&conn, FAUCET)).collect();
+
+        let (batch, _) = claim_queued(&mut conn, 2).unwrap();
+        assert_eq!(ids(&batch), [requests[0].as_str(), requests[1].as_str()]);
+        assert_eq!(count_queued(&conn), Ok(1));
+        assert_eq!(get_queue_position(&conn, &requests[2]), Ok(Some(1)));
+        assert_eq!(get_queue_position(&conn, &requests[0]), Ok(None));
+    }
+
+    #[test]
+    fn requeued_requests_keep_their_attempts() {
+        let mut conn = queue();
+        let request_id = enqueue(&conn, FAUCET);
+        claim_queued(&mut conn, 10).unwrap();
+
+        requeue_requests(&conn, &[request_id.as_str()]).unwrap();
+        let request = request(&conn, &request_id);
+        assert_eq!(request.status, STATUS_QUEUED);
+        assert_eq!(request.attempts, 0);
+    }
+
+    #[test]
+    fn interrupted_batches_are_requeued_but_proving_requests_are_not() {
+        let mut conn = queue();
+        let batching = enqueue(&conn, FAUCET);
+        let proving = enqueue(&conn, FAUCET);
+        claim_queued(&mut conn, 10).unwrap();
+        prove(&conn, &proving, "0xtx");
+
+        assert_eq!(requeue_interrupted(&conn), Ok(1));
+        let request = request(&conn, &batching);
+        assert_eq!(request.status, STATUS_QUEUED);
+        assert_eq!(request.attempts, 1);
+        assert_eq!(
+            ids(&get_proving_requests(&conn).unwrap()),
+            [proving.as_str()]
+        );
+    }
+
+    #[test]
+    fn requeued_transactions_forget_the_transaction_and_count_the_attempt() {
+        let mut conn = queue();
+        let request_id = enqueue(&conn, FAUCET);
+        claim_queued(&mut conn, 10).unwrap();
+        prove(&conn, &request_id, "0xtx");
+
+        assert_eq!(requeue_tx(&conn, "0xtx"), Ok(1));
+        let request = request(&conn, &request_id);
+        assert_eq!(request.status, STATUS_QUEUED);
+        assert_eq!(request.attempts, 1);
+        assert_eq!(request.tx_id, None);
+        assert_eq!(request.note_id, None);
+        assert_eq!(request.nullifier, None);
+    }
+
+    #[test]
+    fn fails_requests_that_ran_out_of_attempts() {
+        let mut conn = queue();
+        let request_id = enqueue(&conn, FAUCET);
+        for _ in 0..MAX_MINT_ATTEMPTS {
+            let (batch, _) = claim_queued(&mut conn, 10).unwrap();
+            assert_eq!(ids(&batch), [request_id.as_str()]);
+            requeue_interrupted(&conn).unwrap();
+        }
+
+        let (batch, exhausted) = claim_queued(&mut conn, 10).unwrap();
+        assert!(batch.is_empty());
+        assert_eq!(exhausted, vec![request_id.clone()]);
+        let request = request(&conn, &request_id);
+        assert_eq!(request.status, STATUS_FAILED);
+        assert_eq!(
+            request.error_code.as_deref(),
+            Some(MintError::AttemptsExhausted.code())
+        );
+        assert!(claim_queued(&mut conn, 10).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn follows_a_transaction_until_it_commits() {
+        let mut conn = queue();
+        let requests: Vec<String> = (0..2).map(|_| enqueue(&conn, FAUCET)).collect();
+        claim_queued(&mut conn, 10).unwrap();
+        for request_id in &requests {
+            prove(&conn, request_id, "0xtx");
+        }
+
+        mark_tx_submitted(&conn, "0xtx").unwrap();
+        assert_eq!(get_submitted_tx_ids(&conn), Ok(vec!["0xtx".to_string()]));
+        assert_eq!(
+            get_request_ids_for_tx(&conn, "0xtx", STATUS_SUBMITTED),
+            Ok(requests.clone())
+        );
+        assert_eq!(minted_in_last_secs(&conn, FAUCET, 60), Ok(200));
+
+        mark_tx_committed(&conn, "0xtx").unwrap();
+        assert!(get_submitted_tx_ids(&conn).unwrap().is_empty());
+        for request_id in &requests {
+            assert_eq!(request(&conn, request_id).status, STATUS_COMMITTED);
+        }
+        assert_eq!(minted_in_last_secs(&conn, FAUCET, 60), Ok(200));
+        assert_eq!(minted_in_last_secs(&conn, OTHER_FAUCET, 60), Ok(0));
+        assert_eq!(
+            get_minted_note(&conn, &format!("0xnote{}", requests[0])),
+            Ok(Some((100, Some(format!("0xnullifier{}", requests[0])))))
+        );
+    }
+
+    #[test]
+    fn fails_the_requests_of_a_discarded_transaction() {
+        let mut conn = queue();
+        let request_id = enqueue(&conn, FAUCET);
+        claim_queued(&mut conn, 10).unwrap();
+        mark_submitted(
+            &conn,
+            &request_id,
+            "0xtx",
+            "0xnote",
+            "mtst1account",
+            "local",
+        )
+        .unwrap();
+
+        let error = MintError::SubmissionRejected("Transaction discarded".to_string());
+        mark_tx_failed(&conn, "0xtx", &error).unwrap();
+        let request = request(&conn, &request_id);
+        assert_eq!(request.status, STATUS_FAILED);
+        assert_eq!(request.error_code.as_deref(), Some(error.code()));
+        assert_eq!(minted_in_last_secs(&conn, FAUCET, 60), Ok(0));
+    }
+
+    #[test]
+    fn undelivered_notes_stay_minted_until_delivered() {
+        let mut conn = queue();
+        let request_id = enqueue(&conn, FAUCET);
+        claim_queued(&mut conn, 10).unwrap();
+        mark_submitted(
+            &conn,
+            &request_id,
+            "0xtx",
+            "0xnote",
+            "mtst1account",
+            "local",
+        )
+        .unwrap();
+
+        let error = MintError::Transport("Note 0xnote was not delivered".to_string());
+        mark_delivery_failed(&conn, &request_id, &error).unwrap();
+        assert_eq!(request(&conn, &request_id).status, STATUS_SUBMITTED);
+        assert_eq!(
+            ids(&get_undelivered_requests(&conn).unwrap()),
+            [request_id.as_str()]
+        );
+        assert_eq!(minted_in_last_secs(&conn, FAUCET, 60), Ok(100));
+
+        mark_tx_committed(&conn, "0xtx").unwrap();
+        assert_eq!(get_undelivered_requests(&conn).unwrap().len(), 1);
+        mark_delivered(&conn, &request_id).unwrap();
+        assert!(get_undelivered_requests(&conn).unwrap().is_empty());
+        assert_eq!(request(&conn, &request_id).delivery_error, None);
+    }
+}
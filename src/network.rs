@@ -0,0 +1,112 @@
+//! Network the binaries talk to, shared by `create-faucet`, the mint server and the tx worker so
+//! the rpc endpoint, bech32 prefix, prover and note transport always agree.
+use miden_client::{account::NetworkId, rpc::Endpoint};
+
+use crate::prover::ProverMode;
+
+/// Network used when `MIDEN_NETWORK` is not set
+pub const DEFAULT_NETWORK: &str = "testnet";
+pub const TESTNET_TX_PROVER_URL: &str = "https://tx-prover.testnet.miden.io";
+pub const DEVNET_TX_PROVER_URL: &str = "https://tx-prover.devnet.miden.io";
+pub const DEFAULT_NOTE_TRANSPORT_URL: &str = "https://transport.miden.io";
+
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    /// `testnet`, `devnet`, `localnet` or `custom`
+    pub name: String,
+    pub endpoint: Endpoint,
+    /// bech32 prefix used for addresses on this network
+    pub network_id: NetworkId,
+    /// remote prover, `None` proves transactions locally
+    pub tx_prover_url: Option<String>,
+    pub prover_mode: ProverMode,
+    /// note transport used to deliver private notes, `None` disables delivery
+    pub note_transport_url: Option<String>,
+}
+
+impl NetworkConfig {
+    /// Defaults for a known network name (`testnet`, `devnet` or `localnet`)
+    pub fn named(name: &str) -> Result<Self, String> {
+        let (endpoint, tx_prover_url, note_transport_url) = match name {
+            "testnet" => (
+                Endpoint::testnet(),
+                Some(TESTNET_TX_PROVER_URL),
+                Some(DEFAULT_NOTE_TRANSPORT_URL),
+            ),
+            "devnet" => (
+                Endpoint::devnet(),
+                Some(DEVNET_TX_PROVER_URL),
+                Some(DEFAULT_NOTE_TRANSPORT_URL),
+            ),
+            "localnet" => (Endpoint::localhost(), None, None),
+            _ => {
+                return Err(format!(
+                    "Unknown network: {}. Use 'testnet', 'devnet' or 'localnet'",
+                    name
+                ));
+            }
+        };
+        Ok(Self {
+            name: name.to_string(),
+            network_id: endpoint.to_network_id(),
+            endpoint,
+            tx_prover_url: tx_prover_url.map(str::to_string),
+            prover_mode: match tx_prover_url {
+                Some(_) => ProverMode::Remote,
+                None => ProverMode::Local,
+            },
+            note_transport_url: note_transport_url.map(str::to_string),
+        })
+    }
+
+    /// Reads `MIDEN_NETWORK` (defaults to testnet), or uses `network` when given, then applies the
+    /// `MIDEN_RPC_URL`, `MIDEN_NETWORK_ID`, `TX_PROVER_URL`, `TX_PROVER_MODE` and
+    /// `NOTE_TRANSPORT_URL` overrides.
+    ///
+    /// `MIDEN_RPC_URL` points the network at a custom node, e.g. `http://127.0.0.1:57291`. The
+    /// prover and note transport overrides accept `none` to prove locally or skip note delivery.
+    pub fn from_env_or(network: Option<&str>) -> Result<Self, String> {
+        let name = match network {
+            Some(network) => network.to_string(),
+            None => std::env::var("MIDEN_NETWORK").unwrap_or_else(|_| DEFAULT_NETWORK.to_string()),
+        };
+        let mut config = Self::named(name.trim())?;
+
+        if let Some(rpc_url) = non_empty_env("MIDEN_RPC_URL") {
+            config.endpoint = Endpoint::try_from(rpc_url.as_str())
+                .map_err(|err| format!("Invalid MIDEN_RPC_URL {}: {}", rpc_url, err))?;
+            if config.endpoint != Self::named(&config.name)?.endpoint {
+                config.name = "custom".to_string();
+                config.network_id = config.endpoint.to_network_id();
+            }
+        }
+        if let Some(network_id) = non_empty_env("MIDEN_NETWORK_ID") {
+            config.network_id = NetworkId::new(&network_id)
+                .map_err(|err| format!("Invalid MIDEN_NETWORK_ID {}: {}", network_id, err))?;
+        }
+        if let Some(url) = non_empty_env("TX_PROVER_URL") {
+            config.tx_prover_url = (url != "none").then_some(url);
+            if config.tx_prover_url.is_none() {
+                config.prover_mode = ProverMode::Local;
+            }
+        }
+        if let Some(mode) = non_empty_env("TX_PROVER_MODE") {
+            config.prover_mode = ProverMode::parse(&mode)?;
+        }
+        if let Some(url) = non_empty_env("NOTE_TRANSPORT_URL") {
+            config.note_transport_url = (url != "none").then_some(url);
+        }
+        Ok(config)
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_env_or(None)
+    }
+}
+
+fn non_empty_env(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
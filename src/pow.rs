@@ -0,0 +1,304 @@
+//! Stateless proof-of-work challenges in front of the mint endpoint.
+//!
+//! A challenge is `account_id (15 bytes) || timestamp (8 bytes BE) || difficulty (1 byte)` followed
+//! by an HMAC-SHA256 of those bytes, hex encoded. A solution is a `u64` nonce such that
+//! `sha256(challenge_bytes || nonce_be)` starts with `difficulty` zero bits.
+use std::{collections::BTreeMap, sync::Mutex};
+
+use hmac::{Hmac, Mac};
+use miden_client::account::AccountId;
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::utils::{env_or, now_secs};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const PAYLOAD_LEN: usize = 15 + 8 + 1;
+const CHALLENGE_LEN: usize = PAYLOAD_LEN + 32;
+
+/// Leading zero bits required with an empty queue
+pub const DEFAULT_POW_BASE_DIFFICULTY: u8 = 16;
+/// Every this many queued requests add one bit of difficulty
+pub const DEFAULT_POW_QUEUE_STEP: usize = 10;
+/// Difficulty never goes above this, so clients can always solve a challenge
+pub const MAX_POW_DIFFICULTY: u8 = 32;
+/// How long a challenge can be used after it was issued
+pub const DEFAULT_POW_CHALLENGE_TTL_SECS: u64 = 5 * 60;
+
+#[derive(Serialize, Debug)]
+pub struct PowChallenge {
+    pub challenge: String,
+    pub difficulty: u8,
+    pub timestamp: u64,
+    pub expires_at: u64,
+}
+
+pub struct PowGate {
+    secret: Vec<u8>,
+    base_difficulty: u8,
+    queue_step: usize,
+    ttl_secs: u64,
+    /// challenges already redeemed, with their expiry time
+    used_challenges: Mutex<BTreeMap<[u8; 32], u64>>,
+}
+
+impl PowGate {
+    /// Reads `POW_SECRET`, `POW_BASE_DIFFICULTY`, `POW_QUEUE_STEP` and `POW_CHALLENGE_TTL_SECS`.
+    ///
+    /// Without `POW_SECRET` a random secret is used, so challenges do not survive a restart.
+    pub fn from_env() -> Self {
+        let secret = match std::env::var("POW_SECRET") {
+            Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+            _ => {
+                let mut secret = vec![0u8; 32];
+                rand::rng().fill_bytes(&mut secret);
+                secret
+            }
+        };
+        Self {
+            secret,
+            base_difficulty: env_or("POW_BASE_DIFFICULTY", DEFAULT_POW_BASE_DIFFICULTY),
+            queue_step: env_or("POW_QUEUE_STEP", DEFAULT_POW_QUEUE_STEP).max(1),
+            ttl_secs: env_or("POW_CHALLENGE_TTL_SECS", DEFAULT_POW_CHALLENGE_TTL_SECS),
+            used_challenges: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Difficulty for a new challenge given the number of requests waiting in the mint queue
+    pub fn difficulty(&self, queue_len: usize) -> u8 {
+        let extra = (queue_len / self.queue_step).min(MAX_POW_DIFFICULTY as usize) as u8;
+        self.base_difficulty
+            .saturating_add(extra)
+            .min(MAX_POW_DIFFICULTY)
+    }
+
+    fn sign(&self, payload: &[u8]) -> [u8; 32] {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(payload);
+        mac.finalize().into_bytes().into()
+    }
+
+    pub fn issue(&self, account_id: AccountId, queue_len: usize) -> PowChallenge {
+        let timestamp = now_secs();
+        let difficulty = self.difficulty(queue_len);
+        let account_bytes: [u8; 15] = account_id.into();
+
+        let mut challenge = Vec::with_capacity(CHALLENGE_LEN);
+        challenge.extend_from_slice(&account_bytes);
+        challenge.extend_from_slice(&timestamp.to_be_bytes());
+        challenge.push(difficulty);
+        let signature = self.sign(&challenge);
+        challenge.extend_from_slice(&signature);
+
+        PowChallenge {
+            challenge: hex::encode(challenge),
+            difficulty,
+            timestamp,
+            expires_at: timestamp + self.ttl_secs,
+        }
+    }
+
+    /// Verifies a solved challenge for `account_id` and marks it as used
+    pub fn verify(&self, account_id: AccountId, challenge: &str, nonce: u64) -> Result<(), String> {
+        let challenge =
+            hex::decode(challenge).map_err(|_| "Invalid challenge encoding".to_string())?;
+        if challenge.len() != CHALLENGE_LEN {
+            return Err("Invalid challenge length".to_string());
+        }
+        let (payload, signature) = challenge.split_at(PAYLOAD_LEN);
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(payload);
+        mac.verify_slice(signature)
+            .map_err(|_| "Invalid challenge signature".to_string())?;
+
+        let account_bytes: [u8; 15] = account_id.into();
+        if payload[..15] != account_bytes {
+            return Err("Challenge was issued for a different account".to_string());
+        }
+        let timestamp = u64::from_be_bytes(payload[15..23].try_into().unwrap());
+        let difficulty = payload[23];
+        let expires_at = timestamp + self.ttl_secs;
+        let now = now_secs();
+        if now > expires_at {
+            return Err("Challenge expired".to_string());
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(&challenge);
+        hasher.update(nonce.to_be_bytes());
+        let hash = hasher.finalize();
+        if leading_zero_bits(&hash) < difficulty as u32 {
+            return Err("Invalid proof of work".to_string());
+        }
+
+        let mut used = self.used_challenges.lock().unwrap();
+        used.retain(|_, expiry| *expiry >= now);
+        let key: [u8; 32] = signature.try_into().unwrap();
+        if used.insert(key, expires_at).is_some() {
+            return Err("Challenge already used".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut count = 0;
+    for byte in bytes {
+        if *byte == 0 {
+            count += 8;
+        } else {
+            count += byte.leading_zeros();
+            break;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "0xcda5ce1aab2c6a2011bff4904e3b8d";
+    const OTHER_ACCOUNT: &str = "0x1862a0efd4027a2036dd9aaac6df74";
+
+    fn account(hex: &str) -> AccountId {
+        AccountId::from_hex(hex).unwrap()
+    }
+
+    fn gate(secret: &[u8]) -> PowGate {
+        PowGate {
+            secret: secret.to_vec(),
+            base_difficulty: 8,
+            queue_step: DEFAULT_POW_QUEUE_STEP,
+            ttl_secs: DEFAULT_POW_CHALLENGE_TTL_SECS,
+            used_challenges: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Signed challenge for `account_id` issued at `timestamp`, like [`PowGate::issue`] builds it
+    fn challenge_at(gate: &PowGate, account_id: AccountId, timestamp: u64) -> String {
+        let account_bytes: [u8; 15] = account_id.into();
+        let mut challenge = account_bytes.to_vec();
+        challenge.extend_from_slice(&timestamp.to_be_bytes());
+        challenge.push(gate.base_difficulty);
+        let signature = gate.sign(&challenge);
+        challenge.extend_from_slice(&signature);
+        hex::encode(challenge)
+    }
+
+    fn solve(challenge: &str, difficulty: u8) -> u64 {
+        let bytes = hex::decode(challenge).unwrap();
+        (0..)
+            .find(|nonce: &u64| {
+                let hash = Sha256::new()
+                    .chain_update(&bytes)
+                    .chain_update(nonce.to_be_bytes())
+                    .finalize();
+                leading_zero_bits(&hash) >= difficulty as u32
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn accepts_a_solved_challenge() {
+        let gate = gate(b"secret");
+        let issued = gate.issue(account(ACCOUNT), 0);
+        let nonce = solve(&issued.challenge, issued.difficulty);
+        assert_eq!(
+            gate.verify(account(ACCOUNT), &issued.challenge, nonce),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_a_replayed_challenge() {
+        let gate = gate(b"secret");
+        let issued = gate.issue(account(ACCOUNT), 0);
+        let nonce = solve(&issued.challenge, issued.difficulty);
+        gate.verify(account(ACCOUNT), &issued.challenge, nonce)
+            .unwrap();
+        assert_eq!(
+            gate.verify(account(ACCOUNT), &issued.challenge, nonce),
+            Err("Challenge already used".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_a_challenge_for_another_account() {
+        let gate = gate(b"secret");
+        let issued = gate.issue(account(ACCOUNT), 0);
+        let nonce = solve(&issued.challenge, issued.difficulty);
+        assert_eq!(
+            gate.verify(account(OTHER_ACCOUNT), &issued.challenge, nonce),
+            Err("Challenge was issued for a different account".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_an_expired_challenge() {
+        let gate = gate(b"secret");
+        let issued_at = now_secs() - gate.ttl_secs - 1;
+        let challenge = challenge_at(&gate, account(ACCOUNT), issued_at);
+        let nonce = solve(&challenge, gate.base_difficulty);
+        assert_eq!(
+            gate.verify(account(ACCOUNT), &challenge, nonce),
+            Err("Challenge expired".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_a_challenge_signed_with_another_secret() {
+        let gate = gate(b"secret");
+        let forged = challenge_at(&self::gate(b"other secret"), account(ACCOUNT), now_secs());
+        let nonce = solve(&forged, gate.base_difficulty);
+        assert_eq!(
+            gate.verify(account(ACCOUNT), &forged, nonce),
+            Err("Invalid challenge signature".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_a_tampered_difficulty() {
+        let gate = gate(b"secret");
+        let issued = gate.issue(account(ACCOUNT), 0);
+        let mut bytes = hex::decode(&issued.challenge).unwrap();
+        bytes[PAYLOAD_LEN - 1] = 0;
+        let tampered = hex::encode(bytes);
+        assert_eq!(
+            gate.verify(account(ACCOUNT), &tampered, 0),
+            Err("Invalid challenge signature".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_a_wrong_nonce() {
+        let gate = gate(b"secret");
+        let issued = gate.issue(account(ACCOUNT), 0);
+        let bytes = hex::decode(&issued.challenge).unwrap();
+        let wrong = (0u64..)
+            .find(|nonce| {
+                let hash = Sha256::new()
+                    .chain_update(&bytes)
+                    .chain_update(nonce.to_be_bytes())
+                    .finalize();
+                leading_zero_bits(&hash) < issued.difficulty as u32
+            })
+            .unwrap();
+        assert_eq!(
+            gate.verify(account(ACCOUNT), &issued.challenge, wrong),
+            Err("Invalid proof of work".to_string())
+        );
+    }
+
+    #[test]
+    fn difficulty_grows_with_the_queue() {
+        let gate = gate(b"secret");
+        assert_eq!(gate.difficulty(0), 8);
+        assert_eq!(gate.difficulty(DEFAULT_POW_QUEUE_STEP), 9);
+        assert_eq!(gate.difficulty(usize::MAX), MAX_POW_DIFFICULTY);
+    }
+}
@@ -0,0 +1,114 @@
+//! Transaction proving, either through the remote prover, in process, or remotely with the local
+//! prover as a fallback when the remote one fails.
+use std::sync::Arc;
+
+use crate::{keystore::FaucetKeyStore, mint_error::MintError, network::NetworkConfig};
+use miden_client::{
+    Client, RemoteTransactionProver,
+    transaction::{
+        LocalTransactionProver, ProvenTransaction, TransactionProver, TransactionResult,
+    },
+};
+
+pub const PROVER_REMOTE: &str = "remote";
+pub const PROVER_LOCAL: &str = "local";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProverMode {
+    Remote,
+    Local,
+    RemoteWithLocalFallback,
+}
+
+impl ProverMode {
+    /// Parses `remote`, `local` or `remote-with-local-fallback`
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "remote" => Ok(Self::Remote),
+            "local" => Ok(Self::Local),
+            "remote-with-local-fallback" | "fallback" => Ok(Self::RemoteWithLocalFallback),
+            _ => Err(format!(
+                "Unknown prover mode: {}. Use 'remote', 'local' or 'remote-with-local-fallback'",
+                mode
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ProverMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Remote => write!(f, "remote"),
+            Self::Local => write!(f, "local"),
+            Self::RemoteWithLocalFallback => write!(f, "remote-with-local-fallback"),
+        }
+    }
+}
+
+/// Provers available to a client, picked per transaction according to the [`ProverMode`]
+#[derive(Clone)]
+pub struct TxProver {
+    pub mode: ProverMode,
+    remote: Option<Arc<RemoteTransactionProver>>,
+    local: Arc<LocalTransactionProver>,
+}
+
+impl TxProver {
+    pub fn from_network(network: &NetworkConfig) -> Result<Self, String> {
+        let remote = match (network.prover_mode, &network.tx_prover_url) {
+            (ProverMode::Local, _) => None,
+            (_, Some(url)) => Some(Arc::new(RemoteTransactionProver::new(url.clone()))),
+            (mode, None) => {
+                return Err(format!(
+                    "Prover mode {} needs a remote prover, set TX_PROVER_URL",
+                    mode
+                ));
+            }
+        };
+        Ok(Self {
+            mode: network.prover_mode,
+            remote,
+            local: Arc::new(LocalTransactionProver::default()),
+        })
+    }
+
+    /// Prover the client uses for transactions that are not proven through [`TxProver::prove`]
+    pub fn default_prover(&self) -> Arc<dyn TransactionProver + Send + Sync> {
+        match &self.remote {
+            Some(remote) => remote.clone(),
+            None => self.local.clone(),
+        }
+    }
+
+    /// Proves `tx_result`, returning the proof and the prover that produced it
+    pub async fn prove(
+        &self,
+        client: &mut Client<FaucetKeyStore>,
+        tx_result: &TransactionResult,
+    ) -> Result<(ProvenTransaction, &'static str), MintError> {
+        if let Some(remote) = &self.remote {
+            match client
+                .prove_transaction_with(tx_result, remote.clone())
+                .await
+            {
+                Ok(proven) => return Ok((proven, PROVER_REMOTE)),
+                Err(err) if self.mode == ProverMode::RemoteWithLocalFallback => {
+                    eprintln!("Remote prover failed, proving locally: {}", err);
+                }
+                Err(err) => {
+                    return Err(MintError::Proving(format!(
+                        "Failed to prove transaction remotely: {}",
+                        err
+                    )));
+                }
+            }
+        }
+        client
+            .prove_transaction_with(tx_result, self.local.clone())
+            .await
+            .map(|proven| (proven, PROVER_LOCAL))
+            .map_err(|err| {
+                MintError::Proving(format!("Failed to prove transaction locally: {}", err))
+            })
+    }
+}
@@ -0,0 +1,193 @@
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::{
+    mint_queue::STATUS_FAILED,
+    utils::{env_or, now_secs},
+};
+
+/// Default cooldown between two mints to the same account or from the same ip
+pub const DEFAULT_MINT_COOLDOWN_SECS: u64 = 24 * 60 * 60;
+
+/// Cooldown window in seconds, read from `MINT_COOLDOWN_SECS`
+pub fn mint_cooldown_secs() -> u64 {
+    env_or("MINT_COOLDOWN_SECS", DEFAULT_MINT_COOLDOWN_SECS)
+}
+
+pub fn create_rate_limit_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS MINT_RATE_LIMITS (
+            key TEXT PRIMARY KEY,
+            last_request INTEGER NOT NULL,
+            -- the mint request that started the cooldown, cleared if it fails
+            request_id TEXT NOT NULL
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Returns the unix timestamp at which the caller can retry if any of the keys is still in its
+/// cooldown, `None` when the request is allowed.
+///
+/// Run it in the same immediate transaction as [`record_rate_limit`] so two concurrent requests
+/// cannot both pass.
+pub fn check_rate_limit(
+    conn: &Connection,
+    keys: &[String],
+    cooldown_secs: u64,
+) -> Result<Option<u64>, String> {
+    let now = now_secs();
+    let mut retry_at = None;
+    for key in keys {
+        let last_request: Option<u64> = conn
+            .query_row(
+                "SELECT last_request FROM MINT_RATE_LIMITS WHERE key = ?1",
+                (key,),
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|err| format!("Failed to read rate limit {}", err))?;
+        if let Some(last_request) = last_request {
+            let next_allowed = last_request + cooldown_secs;
+            if next_allowed > now {
+                retry_at = Some(retry_at.map_or(next_allowed, |t: u64| t.max(next_allowed)));
+            }
+        }
+    }
+    Ok(retry_at)
+}
+
+/// Starts the cooldown of every key for the stored mint request `request_id`
+pub fn record_rate_limit(
+    conn: &Connection,
+    keys: &[String],
+    request_id: &str,
+) -> Result<(), String> {
+    let now = now_secs();
+    for key in keys {
+        conn.execute(
+            "INSERT INTO MINT_RATE_LIMITS (key, last_request, request_id) VALUES (?1, ?2, ?3)
+             ON CONFLICT(key) DO UPDATE SET last_request = excluded.last_request, request_id = excluded.request_id",
+            (key, now, request_id),
+        )
+        .map_err(|err| format!("Failed to record rate limit {}", err))?;
+    }
+    Ok(())
+}
+
+/// Clears the cooldowns started by mint requests that ended up failed, so the caller can try
+/// again right away. Returns how many were cleared.
+pub fn release_failed_rate_limits(conn: &Connection) -> Result<usize, String> {
+    conn.execute(
+        "DELETE FROM MINT_RATE_LIMITS WHERE request_id IN (SELECT request_id FROM MINT_REQUESTS WHERE status = ?1)",
+        (STATUS_FAILED,),
+    )
+    .map_err(|err| format!("Failed to release rate limits {}", err))
+}
+
+/// Seconds left until `retry_at`
+pub fn retry_after_secs(retry_at: u64) -> u64 {
+    retry_at.saturating_sub(now_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use miden_client::note::NoteType;
+
+    use super::*;
+    use crate::{
+        mint_error::MintError,
+        mint_queue::{create_mint_requests_table, enqueue_mint_request, mark_failed},
+    };
+
+    const COOLDOWN_SECS: u64 = 60;
+
+    fn db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        create_rate_limit_table(&conn).unwrap();
+        create_mint_requests_table(&conn).unwrap();
+        conn
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn enqueue(conn: &Connection) -> String {
+        enqueue_mint_request(conn, "0xfaucet", "mtst1address", 100, NoteType::Public).unwrap()
+    }
+
+    #[test]
+    fn starts_the_cooldown_once_recorded() {
+        let conn = db();
+        let account = keys(&["faucet:account:a"]);
+        assert_eq!(check_rate_limit(&conn, &account, COOLDOWN_SECS), Ok(None));
+
+        let before = now_secs();
+        record_rate_limit(&conn, &account, "request").unwrap();
+        let retry_at = check_rate_limit(&conn, &account, COOLDOWN_SECS)
+            .unwrap()
+            .unwrap();
+        assert!(retry_at >= before + COOLDOWN_SECS);
+        assert!(retry_at <= now_secs() + COOLDOWN_SECS);
+        assert_eq!(check_rate_limit(&conn, &account, 0), Ok(None));
+    }
+
+    #[test]
+    fn any_limited_key_blocks_the_request() {
+        let conn = db();
+        record_rate_limit(&conn, &keys(&["faucet:ip:1.2.3.4"]), "request").unwrap();
+
+        let request = keys(&["faucet:account:b", "faucet:ip:1.2.3.4"]);
+        assert!(
+            check_rate_limit(&conn, &request, COOLDOWN_SECS)
+                .unwrap()
+                .is_some()
+        );
+        let other_ip = keys(&["faucet:account:b", "faucet:ip:5.6.7.8"]);
+        assert_eq!(check_rate_limit(&conn, &other_ip, COOLDOWN_SECS), Ok(None));
+    }
+
+    #[test]
+    fn retry_at_is_the_latest_cooldown_end() {
+        let conn = db();
+        let now = now_secs();
+        for (key, last_request) in [("account", now - 10), ("ip", now - 30), ("old", now - 100)] {
+            conn.execute(
+                "INSERT INTO MINT_RATE_LIMITS (key, last_request, request_id) VALUES (?1, ?2, 'request')",
+                (key, last_request),
+            )
+            .unwrap();
+        }
+
+        let retry_at = check_rate_limit(&conn, &keys(&["ip", "account"]), COOLDOWN_SECS);
+        assert_eq!(retry_at, Ok(Some(now - 10 + COOLDOWN_SECS)));
+        assert_eq!(
+            check_rate_limit(&conn, &keys(&["old"]), COOLDOWN_SECS),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn failed_requests_release_their_cooldown() {
+        let conn = db();
+        let failed = enqueue(&conn);
+        let queued = enqueue(&conn);
+        record_rate_limit(&conn, &keys(&["failed"]), &failed).unwrap();
+        record_rate_limit(&conn, &keys(&["queued"]), &queued).unwrap();
+        assert_eq!(release_failed_rate_limits(&conn), Ok(0));
+
+        mark_failed(&conn, &failed, &MintError::AttemptsExhausted).unwrap();
+        assert_eq!(release_failed_rate_limits(&conn), Ok(1));
+        assert_eq!(
+            check_rate_limit(&conn, &keys(&["failed"]), COOLDOWN_SECS),
+            Ok(None)
+        );
+        assert!(
+            check_rate_limit(&conn, &keys(&["queued"]), COOLDOWN_SECS)
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Reclaimable (P2IDE) faucet notes.
+//!
+//! When `MINT_RECLAIM_AFTER_BLOCKS` is set, faucet notes are issued as P2IDE notes that the
+//! faucet itself can consume once the reclaim height has passed. The recall job looks for such
+//! notes that nobody claimed, consumes them back into the faucet account and burns their
+//! tokens with [`RecallBurn`], so recalled tokens no longer count as issued.
+use std::{error::Error, sync::Arc, time::Duration};
+
+use crate::{keystore::FaucetKeyStore, prover::TxProver, utils::env_or};
+use lazy_static::lazy_static;
+use miden_client::{
+    BlockNumber, Client, Word,
+    account::{AccountComponent, AccountId, component::BasicWallet},
+    assembly::{DefaultSourceManager, Library, LibraryPath, Module, ModuleKind},
+    asset::{Asset, FungibleAsset},
+    note::{Note, WellKnownNote},
+    store::{NoteFilter, OutputNoteRecord},
+    transaction::{TransactionId, TransactionKernel, TransactionRequestBuilder},
+};
+
+/// Path of the recall library, its procedures are called as `::miden_faucet::recall::<name>`
+pub const RECALL_LIBRARY_PATH: &str = "miden_faucet::recall";
+
+const RECALL_CODE: &str = "
+use.miden::native_account
+use.miden::faucet
+
+#! Removes a recalled asset from the faucet vault and burns it, lowering the total issuance.
+#!
+#! Inputs:  [ASSET, pad(12)]
+#! Outputs: [ASSET, pad(12)]
+export.burn_recalled_asset
+    exec.native_account::remove_asset
+    # => [ASSET, pad(12)]
+
+    exec.faucet::burn
+    # => [ASSET, pad(12)]
+end
+";
+
+lazy_static! {
+    static ref RECALL_LIBRARY: Library = {
+        let source_manager = Arc::new(DefaultSourceManager::default());
+        let path = LibraryPath::new(RECALL_LIBRARY_PATH).expect("valid library path");
+        let module = Module::parser(ModuleKind::Library)
+            .parse_str(path, RECALL_CODE, source_manager.as_ref())
+            .expect("recall code parses");
+        TransactionKernel::assembler_with_source_manager(source_manager)
+            .assemble_library([module])
+            .expect("recall code compiles")
+    };
+}
+
+/// Faucet component that burns the tokens of recalled notes
+pub struct RecallBurn;
+
+impl RecallBurn {
+    /// MAST root of `burn_recalled_asset`, present in the code of every faucet that can recall
+    pub fn burn_recalled_asset_digest() -> Word {
+        RECALL_LIBRARY
+            .get_procedure_root_by_name(format!("{}::burn_recalled_asset", RECALL_LIBRARY_PATH))
+            .expect("recall library exports burn_recalled_asset")
+    }
+}
+
+impl From<RecallBurn> for AccountComponent {
+    fn from(_: RecallBurn) -> Self {
+        AccountComponent::new(RECALL_LIBRARY.clone(), vec![])
+            .expect("recall component is valid")
+            .with_supports_all_types()
+    }
+}
+
+/// Default time between two runs of the recall job
+pub const DEFAULT_RECLAIM_INTERVAL_SECS: u64 = 10 * 60;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReclaimConfig {
+    /// number of blocks after which an unclaimed note can be recalled, `None` issues plain P2ID notes
+    pub reclaim_after_blocks: Option<u32>,
+    pub interval: Duration,
+}
+
+impl ReclaimConfig {
+    /// Reads `MINT_RECLAIM_AFTER_BLOCKS` and `MINT_RECLAIM_INTERVAL_SECS`
+    pub fn from_env() -> Self {
+        // unset, empty or 0 issue plain P2ID notes
+        let reclaim_after_blocks =
+            Some(env_or("MINT_RECLAIM_AFTER_BLOCKS", 0)).filter(|blocks| *blocks > 0);
+        let interval_secs = env_or("MINT_RECLAIM_INTERVAL_SECS", DEFAULT_RECLAIM_INTERVAL_SECS);
+        Self {
+            reclaim_after_blocks,
+            interval: Duration::from_secs(interval_secs),
+        }
+    }
+
+    /// Reclaim height for a note minted while the client is synced to `sync_height`
+    pub fn reclaim_height(&self, sync_height: BlockNumber) -> Option<BlockNumber> {
+        self.reclaim_after_blocks
+            .map(|blocks| BlockNumber::from(sync_height.as_u32() + blocks))
+    }
+}
+
+/// Returns the reclaim height of a P2IDE note, `None` for any other note or a P2IDE note without one
+pub fn note_reclaim_height(note: &OutputNoteRecord) -> Option<BlockNumber> {
+    let recipient = note.recipient()?;
+    if recipient.script().root() != WellKnownNote::P2IDE.script_root() {
+        return None;
+    }
+    // P2IDE inputs are [target_suffix, target_prefix, reclaim_height, timelock_height]
+    let reclaim_height = recipient.inputs().values().get(2)?.as_int();
+    (reclaim_height != 0).then(|| BlockNumber::from(reclaim_height as u32))
+}
+
+/// Consumes up to `limit` unclaimed faucet notes whose reclaim height has passed back into the
+/// faucet account and burns their tokens. Expects the client to be synced.
+///
+/// Returns the id of the recall transaction, or `None` if there was nothing to recall.
+pub async fn reclaim_expired_notes(
+    client: &mut Client<FaucetKeyStore>,
+    prover: &TxProver,
+    faucet_id: AccountId,
+    limit: usize,
+) -> Result<Option<TransactionId>, Box<dyn Error>> {
+    let faucet = client
+        .get_account(faucet_id)
+        .await?
+        .ok_or("Faucet account is not tracked by the client")?;
+    // consuming a P2IDE note calls the wallet `receive_asset` procedure
+    if !faucet
+        .account()
+        .code()
+        .has_procedure(BasicWallet::receive_asset_digest())
+    {
+        return Err(
+            "Faucet account does not expose receive_asset, notes cannot be recalled".into(),
+        );
+    }
+    // recalled tokens left in the vault would still count as issued
+    if !faucet
+        .account()
+        .code()
+        .has_procedure(RecallBurn::burn_recalled_asset_digest())
+    {
+        return Err(
+            "Faucet account was created before recalled notes were burned, notes cannot be recalled"
+                .into(),
+        );
+    }
+
+    let sync_height = client.get_sync_height().await?;
+    let notes = client.get_output_notes(NoteFilter::Unspent).await?;
+    let expired: Vec<Note> = notes
+        .into_iter()
+        .filter(|note| note.metadata().sender() == faucet_id && !note.is_consumed())
+        .filter(|note| note_reclaim_height(note).is_some_and(|height| height <= sync_height))
+        .filter_map(|note| {
+            let recipient = note.recipient()?.clone();
+            Some(Note::new(
+                note.assets().clone(),
+                *note.metadata(),
+                recipient,
+            ))
+        })
+        .take(limit)
+        .collect();
+    if expired.is_empty() {
+        return Ok(None);
+    }
+
+    println!("Recalling {} expired faucet notes", expired.len());
+    // faucet notes only hold tokens of the faucet itself
+    let mut recalled = 0;
+    for note in &expired {
+        for asset in note.assets().iter() {
+            if let Asset::Fungible(asset) = asset
+                && asset.faucet_id() == faucet_id
+            {
+                recalled += asset.amount();
+            }
+        }
+    }
+    let recalled = Word::from(FungibleAsset::new(faucet_id, recalled)?);
+    let tx_script = client
+        .script_builder()
+        .with_dynamically_linked_library(&RECALL_LIBRARY)?
+        .compile_tx_script(format!(
+            "begin
+                push.{recalled}
+                call.::{RECALL_LIBRARY_PATH}::burn_recalled_asset
+                dropw
+            end"
+        ))?;
+    let transaction_request = TransactionRequestBuilder::new()
+        .unauthenticated_input_notes(expired.into_iter().map(|note| (note, None)))
+        .custom_script(tx_script)
+        .build()?;
+    let tx_result = client
+        .execute_transaction(faucet_id, transaction_request)
+        .await?;
+    let (proven_transaction, prover) = prover.prove(client, &tx_result).await?;
+    let submission_height = client
+        .submit_proven_transaction(proven_transaction, &tx_result)
+        .await?;
+    client
+        .apply_transaction(&tx_result, submission_height)
+        .await?;
+    let tx_id = tx_result.executed_transaction().id();
+    println!(
+        "Recall transaction {} proven by the {} prover",
+        tx_id, prover
+    );
+    Ok(Some(tx_id))
+}
@@ -1,24 +1,33 @@
-use std::{error::Error, io::Read};
+use std::error::Error;
 
 use axum::{Json, Router, extract::Path, http::StatusCode, routing::get};
 use lazy_static::lazy_static;
-use miden_client::{account::AccountId, address::Address};
+use miden_client::{
+    account::component::{BasicFungibleFaucet, FungibleFaucetExt},
+    address::Address,
+    store::Store,
+};
+use miden_client_sqlite_store::SqliteStore;
 use rusqlite::Connection;
 use serde::Serialize;
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 
 use crate::{
+    faucet::{CLIENT_DB, FaucetConfig, configured_faucets},
+    mint_queue::{create_mint_requests_table, minted_in_last_secs},
     tx_worker::{
-        SYNC_BLOCK_FILE, Transaction, get_number_of_tx_for_address, get_transactions_by_account,
-        get_tx_by_id, get_txs_in_last_hour, get_txs_latest,
+        NoteData, Transaction, get_note_by_id, get_number_of_tx_for_address, get_sync_checkpoint,
+        get_transactions_by_account, get_tx_by_id, get_txs_in_last_hour, get_txs_latest,
+        migrate_transactions_table,
     },
-    utils::validate_address,
+    utils::{MINT_DB, validate_address},
 };
 
 lazy_static! {
-    pub static ref FAUCET_ID: AccountId =
-        AccountId::from_hex(&std::env::var("FAUCET_ID").unwrap()).unwrap();
+    /// faucets served by this deployment, the first one is the default
+    pub static ref FAUCETS: Vec<FaucetConfig> =
+        configured_faucets().expect("No faucet configured");
 }
 
 pub const APP_DB: &str = "./app_db.sqlite3";
@@ -56,6 +65,16 @@ async fn get_transaciton_by_id(Path(tx_id): Path<String>) -> Result<Json<Transac
     Ok(Json(tx))
 }
 
+/// An output note and the transaction that consumed it, if it was claimed by an indexed account
+async fn get_note(Path(note_id): Path<String>) -> Result<Json<NoteData>, StatusCode> {
+    let conn = Connection::open(APP_DB).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let note = get_note_by_id(&conn, &note_id).map_err(|err| {
+        println!("{}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    note.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
 #[derive(Serialize)]
 struct Stats {
     total_transactions: u32,
@@ -119,6 +138,75 @@ async fn get_stats() -> Result<Json<Stats>, StatusCode> {
     Ok(Json(stats))
 }
 
+/// Token parameters and supply of the faucet, amounts in base units
+#[derive(Serialize)]
+struct FaucetInfo {
+    faucet_id: String,
+    symbol: String,
+    decimals: u8,
+    max_supply: u64,
+    issued: u64,
+    mintable: u64,
+    minted_last_24h: u64,
+}
+
+/// Reads the faucet from the client store synced by the mint server and the mint journal
+async fn faucet_info(faucet: &FaucetConfig) -> Result<Json<FaucetInfo>, StatusCode> {
+    let faucet_id = faucet.account_id().map_err(|err| {
+        println!("{}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let store = SqliteStore::new(CLIENT_DB.as_str().into())
+        .await
+        .map_err(|err| handle_db_error(Box::new(err)))?;
+    let record = store
+        .get_account(faucet_id)
+        .await
+        .map_err(|err| handle_db_error(Box::new(err)))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let account = record.account();
+    let faucet =
+        BasicFungibleFaucet::try_from(account).map_err(|err| handle_db_error(Box::new(err)))?;
+    let issued = account
+        .get_token_issuance()
+        .map_err(|err| handle_db_error(Box::new(err)))?
+        .as_int();
+    let max_supply = faucet.max_supply().as_int();
+
+    let conn = Connection::open(MINT_DB).map_err(|err| handle_db_error(Box::new(err)))?;
+    let minted_last_24h =
+        minted_in_last_secs(&conn, &faucet_id.to_hex(), 24 * 60 * 60).map_err(|err| {
+            println!("{}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(FaucetInfo {
+        faucet_id: faucet_id.to_hex(),
+        symbol: faucet.symbol().to_string().unwrap_or_default(),
+        decimals: faucet.decimals(),
+        max_supply,
+        issued,
+        mintable: max_supply.saturating_sub(issued),
+        minted_last_24h,
+    }))
+}
+
+/// Default faucet of the deployment
+async fn get_faucet_info() -> Result<Json<FaucetInfo>, StatusCode> {
+    faucet_info(&FAUCETS[0]).await
+}
+
+/// Faucet by hex id or symbol
+async fn get_faucet_info_by_key(
+    Path(faucet): Path<String>,
+) -> Result<Json<FaucetInfo>, StatusCode> {
+    let faucet = FAUCETS
+        .iter()
+        .find(|config| config.matches(&faucet))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    faucet_info(faucet).await
+}
+
 async fn get_txs_latest_api() -> Result<Json<Vec<Transaction>>, String> {
     let conn = Connection::open(APP_DB).map_err(|err| format!("Failed to open DB: {}", err))?;
     let txs = get_txs_latest(&conn)
@@ -127,12 +215,10 @@ async fn get_txs_latest_api() -> Result<Json<Vec<Transaction>>, String> {
 }
 
 async fn get_last_sync_block() -> Result<Json<String>, String> {
-    let mut file = std::fs::File::open(SYNC_BLOCK_FILE)
-        .map_err(|err| format!("Failed to open last synced block file: {}", err))?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .map_err(|err| format!("Failed to read last synced block file: {}", err))?;
-    Ok(Json(contents))
+    let conn = Connection::open(APP_DB).map_err(|err| format!("Failed to open DB: {}", err))?;
+    let checkpoint = get_sync_checkpoint(&conn)
+        .map_err(|err| format!("Failed to read last synced block: {}", err))?;
+    Ok(Json(checkpoint.to_string()))
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -195,9 +281,8 @@ async fn get_tx_count_for_account(Path(address): Path<String>) -> Result<Json<u3
     Ok(Json(res))
 }
 
-pub async fn start_server() -> Result<(), Box<dyn Error>> {
-    dotenvy::dotenv().ok();
-
+/// Builds the CORS layer from `CORS_ALLOWED_ORIGINS`, returns it along with the configured origins
+pub fn cors_layer() -> (CorsLayer, String) {
     // Get CORS allowed origins from environment variable
     let cors_origins = std::env::var("CORS_ALLOWED_ORIGINS").unwrap_or_else(|_| "*".to_string());
 
@@ -220,6 +305,13 @@ pub async fn start_server() -> Result<(), Box<dyn Error>> {
             .allow_methods(Any)
             .allow_headers(Any)
     };
+    (cors_layer, cors_origins)
+}
+
+pub async fn start_server() -> Result<(), Box<dyn Error>> {
+    dotenvy::dotenv().ok();
+
+    let (cors_layer, cors_origins) = cors_layer();
 
     if !std::path::Path::new(APP_DB).exists() {
         // Only create tables if the database file does not exist
@@ -244,17 +336,25 @@ pub async fn start_server() -> Result<(), Box<dyn Error>> {
                 timestamp INTEGER NOT NULL,
                 note_id TEXT NULL DEFAULT NULL,
                 note_type TEXT NULL DEFAULT NULL,
-                note_aux TEXT NULL DEFAULT NULl
+                note_aux TEXT NULL DEFAULT NULl,
+                faucet_id TEXT NULL DEFAULT NULL
             )
         ",
             (),
         )?;
     }
 
+    migrate_transactions_table(&Connection::open(APP_DB)?)?;
+    // the mint journal is owned by the mint server, make sure it exists for /faucet
+    create_mint_requests_table(&Connection::open(MINT_DB)?)?;
+
     let app = Router::new()
         .route("/add/{address}", get(add_address_if_not_there))
         .route("/transaction/{tx_id}", get(get_transaciton_by_id))
+        .route("/note/{note_id}", get(get_note))
         .route("/stats", get(get_stats))
+        .route("/faucet", get(get_faucet_info))
+        .route("/faucet/{faucet}", get(get_faucet_info_by_key))
         .route("/latest-transactions", get(get_txs_latest_api))
         .route("/chart-data", get(get_chart_data))
         .route(
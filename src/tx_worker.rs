@@ -1,14 +1,110 @@
-use rusqlite::{Connection, Row};
+use miden_client::{Word, asset::Asset};
+use rusqlite::{Connection, OptionalExtension, Row};
 
+use crate::utils::{add_column_if_missing, env_or};
+
+/// Checkpoint of older versions, only read to pick up where they stopped
 pub const SYNC_BLOCK_FILE: &str = "./last_sync_block.txt";
-/// Creates a worker that polls raw blocks from the rpc and see if there are changes
-/// made for the rpc
+/// Blocks built on top of a transaction's block before the API reports it as confirmed,
+/// overridden by `TX_CONFIRMATION_DEPTH`
+pub const DEFAULT_CONFIRMATION_DEPTH: u32 = 10;
+/// Blocks fetched ahead of the one being indexed, overridden by `TX_WORKER_PREFETCH_BLOCKS`
+pub const DEFAULT_PREFETCH_BLOCKS: usize = 32;
+
+/// Reads `TX_WORKER_PREFETCH_BLOCKS`, the number of blocks the worker fetches in parallel
+pub fn prefetch_blocks_from_env() -> usize {
+    match env_or("TX_WORKER_PREFETCH_BLOCKS", DEFAULT_PREFETCH_BLOCKS) {
+        0 => DEFAULT_PREFETCH_BLOCKS,
+        blocks => blocks,
+    }
+}
+/// Reads `TX_CONFIRMATION_DEPTH`
+pub fn confirmation_depth_from_env() -> u32 {
+    env_or("TX_CONFIRMATION_DEPTH", DEFAULT_CONFIRMATION_DEPTH)
+}
 
+// Creates a worker that polls raw blocks from the rpc and see if there are changes
+// made for the rpc
+
+/// An output note of an indexed transaction, stored in `NOTES`
 #[derive(serde::Serialize, Debug)]
 pub struct NoteData {
     pub note_id: String,
-    pub note_type: String, // Public Private
+    /// position of the note in the transaction's output notes
+    pub note_index: u32,
+    pub note_type: String, // public, private or encrypted
     pub note_aux: String,
+    /// unknown for notes indexed before `NOTES` existed
+    pub tag: Option<u32>,
+    pub sender: String,
+    /// recipient digest, only known for public notes
+    pub recipient: Option<String>,
+    /// known for public notes and for notes minted by the mint server
+    pub assets: Option<Vec<AssetData>>,
+    /// known for public notes and for notes minted by the mint server, other private notes are
+    /// only matched by id when consumed unauthenticated
+    pub nullifier: Option<String>,
+    /// indexed transaction that consumed the note
+    pub consumed_by_tx: Option<String>,
+}
+
+/// An asset carried by a note, fungible assets have an amount, non-fungible ones an id
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AssetData {
+    /// hex id of the issuing faucet, only its id prefix for non-fungible assets
+    pub faucet_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_id: Option<String>,
+}
+
+impl From<&Asset> for AssetData {
+    fn from(asset: &Asset) -> Self {
+        match asset {
+            Asset::Fungible(asset) => Self {
+                faucet_id: asset.faucet_id().to_hex(),
+                amount: Some(asset.amount()),
+                asset_id: None,
+            },
+            Asset::NonFungible(asset) => Self {
+                faucet_id: asset.faucet_id_prefix().to_hex(),
+                amount: None,
+                asset_id: Some(Word::from(*asset).to_hex()),
+            },
+        }
+    }
+}
+
+/// Value sent in `notes`: fungible amounts summed per faucet, then every non-fungible asset
+pub fn value_moved(notes: &[NoteData]) -> Vec<AssetData> {
+    let mut value: Vec<AssetData> = vec![];
+    for asset in notes
+        .iter()
+        .filter_map(|note| note.assets.as_ref())
+        .flatten()
+    {
+        let total = value.iter_mut().find(|total| {
+            total.amount.is_some() && asset.amount.is_some() && total.faucet_id == asset.faucet_id
+        });
+        match (total, asset.amount) {
+            (Some(total), Some(amount)) => {
+                total.amount = total.amount.map(|total| total.saturating_add(amount))
+            }
+            _ => value.push(asset.clone()),
+        }
+    }
+    value
+}
+
+/// A note consumed by an indexed transaction, stored in `INPUT_NOTES`
+#[derive(serde::Serialize, Debug)]
+pub struct InputNoteData {
+    pub nullifier: String,
+    /// known for unauthenticated notes and for notes created by an indexed transaction
+    pub note_id: Option<String>,
+    /// indexed transaction that created the note
+    pub created_by_tx: Option<String>,
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -17,56 +113,330 @@ pub struct Transaction {
     pub tx_kind: String,
     pub sender: String,
     pub block_num: u32,
-    pub note_id: Option<NoteData>,
+    pub notes: Vec<NoteData>,
+    pub input_notes: Vec<InputNoteData>,
+    /// value sent in the output notes whose assets are known, see [`value_moved`]
+    pub value: Vec<AssetData>,
     pub timestamp: u32,
+    /// blocks indexed on top of the transaction's block
+    pub confirmations: u32,
+    /// whether `confirmations` reached the confirmation depth, until then a reorg can drop it
+    pub confirmed: bool,
+    /// hex id of the faucet that minted, only set for `faucet_request` transactions
+    pub faucet_id: Option<String>,
 }
 
-impl Transaction {
-    pub fn into_sql_value(
-        self,
-    ) -> (
-        String,
-        String,
-        String,
-        String,
-        String,
-        String,
-        String,
-        String,
-    ) {
-        let (note_id, note_type, note_aux) = self.note_id.map_or(
-            ("NULL".to_string(), "NULL".to_string(), "NULL".to_string()),
-            |val| (val.note_id, val.note_type, val.note_aux),
+/// Adds the columns and tables introduced after `TRANSACTIONS_DETAIL` was first created
+pub fn migrate_transactions_table(conn: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(
+        conn,
+        "TRANSACTIONS_DETAIL",
+        "faucet_id",
+        "TEXT NULL DEFAULT NULL",
+    )?;
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS NOTES (
+            note_id TEXT PRIMARY KEY,
+            tx_id TEXT NOT NULL,
+            note_index INTEGER NOT NULL,
+            note_type TEXT NOT NULL,
+            note_aux TEXT NOT NULL,
+            tag INTEGER NULL DEFAULT NULL,
+            sender TEXT NOT NULL,
+            recipient TEXT NULL DEFAULT NULL,
+            assets TEXT NULL DEFAULT NULL,
+            nullifier TEXT NULL DEFAULT NULL
+        );
+        CREATE INDEX IF NOT EXISTS NOTES_TX_ID ON NOTES (tx_id);
+        CREATE INDEX IF NOT EXISTS NOTES_NULLIFIER ON NOTES (nullifier);
+        -- transactions indexed before NOTES existed kept their first note inline
+        INSERT OR IGNORE INTO NOTES (note_id, tx_id, note_index, note_type, note_aux, sender)
+            SELECT note_id, tx_id, 0, note_type, note_aux, sender FROM TRANSACTIONS_DETAIL
+            WHERE note_id IS NOT NULL AND note_id != 'NULL';
+        CREATE TABLE IF NOT EXISTS INPUT_NOTES (
+            nullifier TEXT PRIMARY KEY,
+            note_id TEXT NULL DEFAULT NULL,
+            tx_id TEXT NOT NULL,
+            created_by_tx TEXT NULL DEFAULT NULL
+        );
+        CREATE INDEX IF NOT EXISTS INPUT_NOTES_TX_ID ON INPUT_NOTES (tx_id);
+        CREATE INDEX IF NOT EXISTS INPUT_NOTES_NOTE_ID ON INPUT_NOTES (note_id);
+        -- commitments of indexed blocks, the highest one is the indexer checkpoint
+        CREATE TABLE IF NOT EXISTS INDEXED_BLOCKS (
+            block_num INTEGER PRIMARY KEY,
+            commitment TEXT NOT NULL
         );
+        ",
+    )
+}
+
+/// Records that every block up to `block_num` is indexed, `commitment` is its header commitment
+pub fn record_indexed_block(
+    conn: &Connection,
+    block_num: u32,
+    commitment: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT OR REPLACE INTO INDEXED_BLOCKS (block_num, commitment) VALUES (?1, ?2)",
+        (block_num, commitment),
+    )
+    .map_err(|err| format!("Failed to record block {} {}", block_num, err))?;
+    Ok(())
+}
+
+/// Highest recorded block below `below` and its commitment
+pub fn get_indexed_block_below(
+    conn: &Connection,
+    below: u32,
+) -> Result<Option<(u32, String)>, String> {
+    conn.query_row(
+        "SELECT block_num, commitment FROM INDEXED_BLOCKS WHERE block_num < ?1 ORDER BY block_num DESC LIMIT 1",
+        (below,),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|err| format!("Failed to get indexed block {}", err))
+}
+
+/// Highest block the worker indexed
+pub fn get_indexed_tip(conn: &Connection) -> Result<Option<u32>, String> {
+    conn.query_row("SELECT MAX(block_num) FROM INDEXED_BLOCKS", (), |row| {
+        row.get(0)
+    })
+    .map_err(|err| format!("Failed to get indexed tip {}", err))
+}
+
+/// Next block to index, falling back to the checkpoint file of older versions
+pub fn get_sync_checkpoint(conn: &Connection) -> Result<u32, String> {
+    Ok(match get_indexed_tip(conn)? {
+        Some(tip) => tip + 1,
+        None => std::fs::read_to_string(SYNC_BLOCK_FILE)
+            .ok()
+            .and_then(|s| s.trim().parse::<u32>().ok())
+            .unwrap_or(1),
+    })
+}
+
+/// Deletes everything indexed above `block_num`, returns how many transactions were dropped
+pub fn rollback_above(conn: &Connection, block_num: u32) -> Result<usize, String> {
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|err| format!("Failed to roll back {}", err))?;
+    let dropped_txs = "SELECT tx_id FROM TRANSACTIONS_DETAIL WHERE block_num > ?1";
+    tx.execute(
+        &format!("DELETE FROM NOTES WHERE tx_id IN ({})", dropped_txs),
+        (block_num,),
+    )
+    .and_then(|_| {
+        tx.execute(
+            &format!("DELETE FROM INPUT_NOTES WHERE tx_id IN ({})", dropped_txs),
+            (block_num,),
+        )
+    })
+    .map_err(|err| format!("Failed to roll back notes {}", err))?;
+    let dropped = tx
+        .execute(
+            "DELETE FROM TRANSACTIONS_DETAIL WHERE block_num > ?1",
+            (block_num,),
+        )
+        .map_err(|err| format!("Failed to roll back transactions {}", err))?;
+    tx.execute(
+        "DELETE FROM INDEXED_BLOCKS WHERE block_num > ?1",
+        (block_num,),
+    )
+    .map_err(|err| format!("Failed to roll back blocks {}", err))?;
+    tx.commit()
+        .map_err(|err| format!("Failed to roll back {}", err))?;
+    Ok(dropped)
+}
+
+/// `NOTES` columns in insert order, see [`NoteData::into_sql_value`]
+pub type NoteSqlValue = (
+    String,
+    String,
+    u32,
+    String,
+    String,
+    Option<u32>,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+impl NoteData {
+    pub fn into_sql_value(self, tx_id: &str) -> NoteSqlValue {
+        (
+            self.note_id,
+            tx_id.to_string(),
+            self.note_index,
+            self.note_type,
+            self.note_aux,
+            self.tag,
+            self.sender,
+            self.recipient,
+            self.assets
+                .map(|assets| serde_json::to_string(&assets).expect("assets are serializable")),
+            self.nullifier,
+        )
+    }
+
+    pub fn from_sql_row(row: &Row) -> rusqlite::Result<Self> {
+        let assets: Option<String> = row.get("assets")?;
+        Ok(Self {
+            note_id: row.get("note_id")?,
+            note_index: row.get("note_index")?,
+            note_type: row.get("note_type")?,
+            note_aux: row.get("note_aux")?,
+            tag: row.get("tag")?,
+            sender: row.get("sender")?,
+            recipient: row.get("recipient")?,
+            assets: assets.and_then(|assets| serde_json::from_str(&assets).ok()),
+            nullifier: row.get("nullifier")?,
+            consumed_by_tx: row.get("consumed_by_tx")?,
+        })
+    }
+}
+
+/// Selects `NOTES` rows along with the transaction that consumed them
+const SELECT_NOTES: &str = "SELECT NOTES.*, INPUT_NOTES.tx_id AS consumed_by_tx FROM NOTES
+    LEFT JOIN INPUT_NOTES ON INPUT_NOTES.note_id = NOTES.note_id
+        OR INPUT_NOTES.nullifier = NOTES.nullifier";
+
+/// Output notes of `tx_id` in the order the transaction created them
+pub fn get_notes_for_tx(conn: &Connection, tx_id: &str) -> Result<Vec<NoteData>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} WHERE NOTES.tx_id = ?1 ORDER BY NOTES.note_index",
+            SELECT_NOTES
+        ))
+        .map_err(|err| format!("Failed to get notes {}", err))?;
+    let rows = stmt
+        .query_map([tx_id], NoteData::from_sql_row)
+        .map_err(|err| format!("Failed to get notes {}", err))?;
+    let mut res = vec![];
+    for row in rows {
+        res.push(row.map_err(|err| format!("Error getting notes {}", err))?);
+    }
+    Ok(res)
+}
+
+/// An indexed output note, `None` if no indexed transaction created it
+pub fn get_note_by_id(conn: &Connection, note_id: &str) -> Result<Option<NoteData>, String> {
+    let mut stmt = conn
+        .prepare(&format!("{} WHERE NOTES.note_id = ?1", SELECT_NOTES))
+        .map_err(|err| format!("Failed to get note {}", err))?;
+    stmt.query_row([note_id], NoteData::from_sql_row)
+        .optional()
+        .map_err(|err| format!("Failed to get note {}", err))
+}
+
+/// Resolves the note id and creator of a consumed note from the notes indexed so far
+pub fn resolve_input_note(
+    conn: &Connection,
+    nullifier: &str,
+    note_id: Option<String>,
+) -> Result<InputNoteData, String> {
+    let created = conn
+        .query_row(
+            "SELECT note_id, tx_id FROM NOTES WHERE note_id = ?1 OR nullifier = ?2",
+            (&note_id, nullifier),
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+        .map_err(|err| format!("Failed to get note {}", err))?;
+    Ok(match created {
+        Some((note_id, tx_id)) => InputNoteData {
+            nullifier: nullifier.to_string(),
+            note_id: Some(note_id),
+            created_by_tx: Some(tx_id),
+        },
+        None => InputNoteData {
+            nullifier: nullifier.to_string(),
+            note_id,
+            created_by_tx: None,
+        },
+    })
+}
+
+impl InputNoteData {
+    pub fn into_sql_value(self, tx_id: &str) -> (String, Option<String>, String, Option<String>) {
+        (
+            self.nullifier,
+            self.note_id,
+            tx_id.to_string(),
+            self.created_by_tx,
+        )
+    }
+
+    pub fn from_sql_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            nullifier: row.get("nullifier")?,
+            note_id: row.get("note_id")?,
+            created_by_tx: row.get("created_by_tx")?,
+        })
+    }
+}
+
+/// Notes consumed by `tx_id`
+pub fn get_input_notes_for_tx(
+    conn: &Connection,
+    tx_id: &str,
+) -> Result<Vec<InputNoteData>, String> {
+    let mut stmt = conn
+        .prepare("SELECT * FROM INPUT_NOTES WHERE tx_id = ?1")
+        .map_err(|err| format!("Failed to get input notes {}", err))?;
+    let rows = stmt
+        .query_map([tx_id], InputNoteData::from_sql_row)
+        .map_err(|err| format!("Failed to get input notes {}", err))?;
+    let mut res = vec![];
+    for row in rows {
+        res.push(row.map_err(|err| format!("Error getting input notes {}", err))?);
+    }
+    Ok(res)
+}
+
+/// Fills in the notes and confirmations of transactions read with [`Transaction::from_sql_row`]
+fn with_details(conn: &Connection, mut txs: Vec<Transaction>) -> Result<Vec<Transaction>, String> {
+    let tip = get_indexed_tip(conn)?.unwrap_or(0);
+    let depth = confirmation_depth_from_env();
+    for tx in txs.iter_mut() {
+        tx.confirmations = tip.saturating_sub(tx.block_num);
+        tx.confirmed = tx.confirmations >= depth;
+        tx.notes = get_notes_for_tx(conn, &tx.tx_id)?;
+        tx.input_notes = get_input_notes_for_tx(conn, &tx.tx_id)?;
+        tx.value = value_moved(&tx.notes);
+    }
+    Ok(txs)
+}
+
+impl Transaction {
+    pub fn into_sql_value(self) -> (String, String, String, String, String, Option<String>) {
         (
             self.block_num.to_string(),
             self.tx_id,
             self.tx_kind,
             self.sender,
             self.timestamp.to_string(),
-            note_id,
-            note_type,
-            note_aux,
+            self.faucet_id,
         )
     }
 
+    /// Reads a `TRANSACTIONS_DETAIL` row, the notes are loaded separately from `NOTES`
     pub fn from_sql_row(row: &Row) -> Self {
-        let mut note_data = None;
-        if row.get::<usize, String>(6).unwrap() != "NULL" {
-            note_data = Some(NoteData {
-                note_id: row.get(6).unwrap(),
-                note_type: row.get(7).unwrap(),
-                note_aux: row.get(8).unwrap(),
-            })
-        }
-
         Self {
             block_num: row.get(1).unwrap(),
             tx_id: row.get(2).unwrap(),
             tx_kind: row.get(3).unwrap(),
             sender: row.get(4).unwrap(),
             timestamp: row.get(5).unwrap(),
-            note_id: note_data,
+            notes: vec![],
+            input_notes: vec![],
+            value: vec![],
+            confirmations: 0,
+            confirmed: false,
+            faucet_id: row.get(9).unwrap(),
         }
     }
 }
@@ -88,7 +458,8 @@ pub fn get_tx_by_id(conn: &Connection, tx_id: String) -> Result<Transaction, Str
     if rows.count() > 0 {
         return Err("Failed".to_string());
     };
-    res
+    let tx = res?;
+    Ok(with_details(conn, vec![tx])?.remove(0))
 }
 
 pub fn get_txs_in_last_hour(conn: &Connection) -> Result<u32, String> {
@@ -123,7 +494,7 @@ pub fn get_txs_latest(conn: &Connection) -> Result<Vec<Transaction>, String> {
     for row in rows {
         res.push(row.map_err(|err| format!("Error getting transactions {}", err))?);
     }
-    Ok(res)
+    with_details(conn, res)
 }
 
 // assumes account_id is a valid bech32 encoded account id
@@ -148,7 +519,7 @@ pub fn get_transactions_by_account(
     for row in rows {
         res.push(row.map_err(|err| format!("Error getting transactions {}", err))?);
     }
-    Ok(res)
+    with_details(conn, res)
 }
 
 pub fn get_number_of_tx_for_address(conn: &Connection, account_id: &str) -> Result<u32, String> {
@@ -169,3 +540,199 @@ pub fn get_number_of_tx_for_address(conn: &Connection, account_id: &str) -> Resu
     };
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAUCET: &str = "0xcda5ce1aab2c6a2011bff4904e3b8d";
+    const OTHER_FAUCET: &str = "0x1862a0efd4027a2036dd9aaac6df74";
+
+    fn fungible(faucet_id: &str, amount: u64) -> AssetData {
+        AssetData {
+            faucet_id: faucet_id.to_string(),
+            amount: Some(amount),
+            asset_id: None,
+        }
+    }
+
+    fn non_fungible(faucet_id: &str, asset_id: &str) -> AssetData {
+        AssetData {
+            faucet_id: faucet_id.to_string(),
+            amount: None,
+            asset_id: Some(asset_id.to_string()),
+        }
+    }
+
+    fn note(assets: Option<Vec<AssetData>>) -> NoteData {
+        NoteData {
+            note_id: "0x01".to_string(),
+            note_index: 0,
+            note_type: "public".to_string(),
+            note_aux: "0".to_string(),
+            tag: None,
+            sender: FAUCET.to_string(),
+            recipient: None,
+            assets,
+            nullifier: None,
+            consumed_by_tx: None,
+        }
+    }
+
+    #[test]
+    fn sums_fungible_amounts_per_faucet() {
+        let notes = [
+            note(Some(vec![fungible(FAUCET, 10), fungible(OTHER_FAUCET, 1)])),
+            note(Some(vec![fungible(FAUCET, 5)])),
+            note(Some(vec![fungible(OTHER_FAUCET, 2)])),
+        ];
+        assert_eq!(
+            value_moved(&notes),
+            vec![fungible(FAUCET, 15), fungible(OTHER_FAUCET, 3)]
+        );
+    }
+
+    #[test]
+    fn saturates_instead_of_overflowing() {
+        let notes = [
+            note(Some(vec![fungible(FAUCET, u64::MAX)])),
+            note(Some(vec![fungible(FAUCET, 1)])),
+        ];
+        assert_eq!(value_moved(&notes), vec![fungible(FAUCET, u64::MAX)]);
+    }
+
+    #[test]
+    fn lists_non_fungible_assets_individually() {
+        let notes = [
+            note(Some(vec![
+                non_fungible(FAUCET, "0xaa"),
+                fungible(FAUCET, 7),
+            ])),
+            note(Some(vec![non_fungible(FAUCET, "0xbb")])),
+        ];
+        assert_eq!(
+            value_moved(&notes),
+            vec![
+                non_fungible(FAUCET, "0xaa"),
+                fungible(FAUCET, 7),
+                non_fungible(FAUCET, "0xbb"),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_notes_without_known_assets() {
+        let notes = [
+            note(None),
+            note(Some(vec![])),
+            note(Some(vec![fungible(FAUCET, 4)])),
+        ];
+        assert_eq!(value_moved(&notes), vec![fungible(FAUCET, 4)]);
+        assert!(value_moved(&[note(None)]).is_empty());
+    }
+
+    /// In-memory indexer database, `TRANSACTIONS_DETAIL` as created by the server
+    fn indexed_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE TRANSACTIONS_DETAIL (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                block_num INTEGER NOT NULL,
+                tx_id TEXT NOT NULL UNIQUE,
+                tx_kind TEXT CHECK(tx_kind IN ('faucet_request', 'send', 'receive')) NOT NULL,
+                sender TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                note_id TEXT NULL DEFAULT NULL,
+                note_type TEXT NULL DEFAULT NULL,
+                note_aux TEXT NULL DEFAULT NULl
+            );
+            ",
+        )
+        .unwrap();
+        migrate_transactions_table(&conn).unwrap();
+        conn
+    }
+
+    /// Indexes block `block_num` with one transaction creating one note and consuming another
+    fn index_block(conn: &Connection, block_num: u32) {
+        let tx_id = format!("0xtx{}", block_num);
+        conn.execute(
+            "INSERT INTO TRANSACTIONS_DETAIL (block_num, tx_id, tx_kind, sender, timestamp) VALUES (?1, ?2, 'send', ?3, 0)",
+            (block_num, &tx_id, FAUCET),
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO NOTES (note_id, tx_id, note_index, note_type, note_aux, sender) VALUES (?1, ?2, 0, 'public', '0', ?3)",
+            (format!("0xnote{}", block_num), &tx_id, FAUCET),
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO INPUT_NOTES (nullifier, tx_id) VALUES (?1, ?2)",
+            (format!("0xnullifier{}", block_num), &tx_id),
+        )
+        .unwrap();
+        record_indexed_block(conn, block_num, &format!("0xblock{}", block_num)).unwrap();
+    }
+
+    fn count(conn: &Connection, table: &str) -> u32 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), (), |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn rollback_drops_everything_above_the_block() {
+        let conn = indexed_db();
+        for block_num in 5..=8 {
+            index_block(&conn, block_num);
+        }
+
+        assert_eq!(rollback_above(&conn, 6), Ok(2));
+        for table in [
+            "TRANSACTIONS_DETAIL",
+            "NOTES",
+            "INPUT_NOTES",
+            "INDEXED_BLOCKS",
+        ] {
+            assert_eq!(count(&conn, table), 2, "{}", table);
+        }
+        assert_eq!(get_indexed_tip(&conn), Ok(Some(6)));
+        assert_eq!(get_sync_checkpoint(&conn), Ok(7));
+        assert!(get_note_by_id(&conn, "0xnote6").unwrap().is_some());
+        assert!(get_note_by_id(&conn, "0xnote7").unwrap().is_none());
+    }
+
+    #[test]
+    fn rollback_at_the_tip_keeps_everything() {
+        let conn = indexed_db();
+        for block_num in 5..=6 {
+            index_block(&conn, block_num);
+        }
+
+        assert_eq!(rollback_above(&conn, 6), Ok(0));
+        assert_eq!(count(&conn, "TRANSACTIONS_DETAIL"), 2);
+        assert_eq!(count(&conn, "NOTES"), 2);
+        assert_eq!(count(&conn, "INPUT_NOTES"), 2);
+        assert_eq!(get_indexed_tip(&conn), Ok(Some(6)));
+    }
+
+    #[test]
+    fn rollback_can_be_indexed_again() {
+        let conn = indexed_db();
+        for block_num in 5..=7 {
+            index_block(&conn, block_num);
+        }
+
+        assert_eq!(rollback_above(&conn, 5), Ok(2));
+        for block_num in 6..=7 {
+            index_block(&conn, block_num);
+        }
+        assert_eq!(count(&conn, "TRANSACTIONS_DETAIL"), 3);
+        assert_eq!(
+            get_indexed_block_below(&conn, 7),
+            Ok(Some((6, "0xblock6".to_string())))
+        );
+    }
+}
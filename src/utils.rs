@@ -1,23 +1,68 @@
-use std::{collections::BTreeSet, error::Error, sync::Arc, time::Instant};
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    str::FromStr,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use miden_client::{
-    Client, RemoteTransactionProver,
+    Client,
     account::AccountId,
     address::Address,
     builder::ClientBuilder,
-    keystore::FilesystemKeyStore,
     note_transport::grpc::GrpcNoteTransportClient,
-    rpc::{Endpoint, GrpcClient},
+    rpc::GrpcClient,
     store::{NoteFilter, TransactionFilter},
     sync::StateSync,
 };
 use miden_client_sqlite_store::SqliteStore;
-use rand::rngs::StdRng;
+use rusqlite::Connection;
+
+use crate::{
+    keystore::FaucetKeyStore, network::NetworkConfig, note_screener::NoteScreener, prover::TxProver,
+};
+
+/// database owned by the mint server (rate limits etc.)
+pub const MINT_DB: &str = "./mint_db.sqlite3";
+
+/// Adds a column to a table created by an older version of the server
+pub fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<&str, String>("name"))?
+        .collect::<rusqlite::Result<Vec<_>>>()?
+        .iter()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            (),
+        )?;
+    }
+    Ok(())
+}
 
-use crate::note_screener::NoteScreener;
+/// Current unix time in seconds
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs()
+}
 
-const TX_PROVER_ENDPOINT: &str = "https://tx-prover.testnet.miden.io";
-const NOTE_TRANSPORT_URL: &str = "https://transport.miden.io";
+/// Parses the environment variable `name`, `default` when it is unset or does not parse
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(default)
+}
 
 pub fn validate_address(bech32_string: &str) -> bool {
     match Address::decode(bech32_string) {
@@ -26,10 +71,10 @@ pub fn validate_address(bech32_string: &str) -> bool {
     }
 }
 
-/// override the default sync state for client
+/// override the default sync state for client, only tracking the headers of `account_ids`
 pub async fn sync_state(
-    account_id: AccountId,
-    client: &mut Client<FilesystemKeyStore<StdRng>>,
+    account_ids: &[AccountId],
+    client: &mut Client<FaucetKeyStore>,
     sqlite_store: Arc<SqliteStore>,
     rpc: Arc<GrpcClient>,
 ) -> Result<(), Box<dyn Error>> {
@@ -37,14 +82,16 @@ pub async fn sync_state(
 
     let state_sync_component = StateSync::new(rpc, Arc::new(note_screener), None);
 
-    let accounts = client
-        .get_account_header_by_id(account_id)
-        .await?
-        .map(|(header, _)| vec![header])
-        .unwrap_or_default();
+    let mut accounts = vec![];
+    for account_id in account_ids {
+        if let Some((header, _)) = client.get_account_header_by_id(*account_id).await? {
+            accounts.push(header);
+        }
+    }
     let note_tags = BTreeSet::new();
     let input_notes = vec![];
-    let expected_output_notes = client.get_output_notes(NoteFilter::Expected).await?;
+    // committed notes are tracked too, so they are marked consumed once claimed
+    let unspent_output_notes = client.get_output_notes(NoteFilter::Unspent).await?;
     let uncommitted_transactions = client
         .get_transactions(TransactionFilter::Uncommitted)
         .await?;
@@ -55,75 +102,114 @@ pub async fn sync_state(
             accounts,
             note_tags,
             input_notes,
-            expected_output_notes,
+            unspent_output_notes,
             uncommitted_transactions,
         )
-        .await
-        .expect("failed to sync state");
-    client
-        .apply_state_sync(state_sync_update)
-        .await
-        .expect("failed to apply state sync");
+        .await?;
+    client.apply_state_sync(state_sync_update).await?;
 
     Ok(())
 }
 
-pub async fn init_client(
+/// Timeout of the rpc and note transport clients
+const RPC_TIMEOUT_MS: u64 = 10_000;
+
+/// Builds a client backed by `client_db`, talking to the rpc endpoint and note transport of
+/// `network`, proving with the default prover of `prover` and signing with `keystore`
+pub async fn build_client(
     client_db: &str,
-    endpoint: Endpoint,
-) -> Client<FilesystemKeyStore<StdRng>> {
-    let timeout_ms = 10_000;
-    let rpc_api = Arc::new(GrpcClient::new(&endpoint, timeout_ms));
-    let sqlite_store = SqliteStore::new(client_db.into()).await.unwrap();
-
-    let note_tranport =
-        GrpcNoteTransportClient::connect(NOTE_TRANSPORT_URL.to_string(), timeout_ms)
-            .await
-            .unwrap();
-    let remote_prover = Arc::new(RemoteTransactionProver::new(TX_PROVER_ENDPOINT.to_string()));
-    ClientBuilder::new()
-        .store(Arc::new(sqlite_store))
+    network: &NetworkConfig,
+    prover: &TxProver,
+    keystore: Arc<FaucetKeyStore>,
+) -> Result<Client<FaucetKeyStore>, Box<dyn Error>> {
+    let rpc_api = Arc::new(GrpcClient::new(&network.endpoint, RPC_TIMEOUT_MS));
+    let sqlite_store = Arc::new(SqliteStore::new(client_db.into()).await?);
+    build_client_with(sqlite_store, rpc_api, network, prover, keystore).await
+}
+
+/// Builds a client on an already opened store and rpc client, so they can be reused for syncing
+async fn build_client_with(
+    sqlite_store: Arc<SqliteStore>,
+    rpc_api: Arc<GrpcClient>,
+    network: &NetworkConfig,
+    prover: &TxProver,
+    keystore: Arc<FaucetKeyStore>,
+) -> Result<Client<FaucetKeyStore>, Box<dyn Error>> {
+    let mut builder = ClientBuilder::new()
+        .store(sqlite_store)
         .rpc(rpc_api)
-        .filesystem_keystore("./keystore")
+        .authenticator(keystore)
         .in_debug_mode(true.into())
-        .note_transport(Arc::new(note_tranport))
-        .prover(remote_prover)
-        .build()
-        .await
-        .expect("Failed to build client")
+        .prover(prover.default_prover());
+    if let Some(url) = &network.note_transport_url {
+        let note_tranport = GrpcNoteTransportClient::connect(url.clone(), RPC_TIMEOUT_MS).await?;
+        builder = builder.note_transport(Arc::new(note_tranport));
+    }
+    Ok(builder.build().await?)
 }
 
-pub async fn init_client_with_custom_sync_state(
+pub async fn init_client(
     client_db: &str,
-    endpoint: Endpoint,
-    faucet_id: AccountId,
-) -> Client<FilesystemKeyStore<StdRng>> {
-    let timeout_ms = 10_000;
-    let rpc_api = Arc::new(GrpcClient::new(&endpoint, timeout_ms));
-    let sqlite_store = SqliteStore::new(client_db.into()).await.unwrap();
-
-    let note_tranport =
-        GrpcNoteTransportClient::connect(NOTE_TRANSPORT_URL.to_string(), timeout_ms)
-            .await
-            .unwrap();
-    let remote_prover = Arc::new(RemoteTransactionProver::new(TX_PROVER_ENDPOINT.to_string()));
-    let mut client = ClientBuilder::new()
-        .store(Arc::new(sqlite_store))
-        .rpc(rpc_api)
-        .filesystem_keystore("./keystore")
-        .in_debug_mode(true.into())
-        .note_transport(Arc::new(note_tranport))
-        .prover(remote_prover)
-        .build()
+    network: &NetworkConfig,
+    keystore: Arc<FaucetKeyStore>,
+) -> Client<FaucetKeyStore> {
+    let prover = TxProver::from_network(network).expect("Invalid prover configuration");
+    build_client(client_db, network, &prover, keystore)
         .await
-        .expect("Failed to build client");
-    client.ensure_genesis_in_place().await.unwrap();
-    let time = Instant::now();
-    let rpc_api = Arc::new(GrpcClient::new(&endpoint, timeout_ms));
-    let sqlite_store = SqliteStore::new(client_db.into()).await.unwrap();
-    sync_state(faucet_id, &mut client, Arc::new(sqlite_store), rpc_api)
-        .await
-        .unwrap();
-    println!("State synced in {:?}", time.elapsed());
-    client
+        .expect("Failed to build client")
+}
+
+/// Client owned by the faucet for its whole lifetime, synced incrementally with the custom
+/// [`sync_state`] that only tracks the faucet accounts and their output notes
+pub struct FaucetClient {
+    pub client: Client<FaucetKeyStore>,
+    /// prover used for mint transactions, reports whether the remote or local prover was used
+    pub prover: TxProver,
+    /// store and rpc client of `client`, reused by every sync
+    sqlite_store: Arc<SqliteStore>,
+    rpc_api: Arc<GrpcClient>,
+    faucet_ids: Vec<AccountId>,
+}
+
+impl FaucetClient {
+    pub async fn new(
+        client_db: &str,
+        network: NetworkConfig,
+        faucet_ids: Vec<AccountId>,
+        keystore: Arc<FaucetKeyStore>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let prover = TxProver::from_network(&network)?;
+        let rpc_api = Arc::new(GrpcClient::new(&network.endpoint, RPC_TIMEOUT_MS));
+        let sqlite_store = Arc::new(SqliteStore::new(client_db.into()).await?);
+        let mut client = build_client_with(
+            sqlite_store.clone(),
+            rpc_api.clone(),
+            &network,
+            &prover,
+            keystore,
+        )
+        .await?;
+        client.ensure_genesis_in_place().await?;
+        Ok(Self {
+            client,
+            prover,
+            sqlite_store,
+            rpc_api,
+            faucet_ids,
+        })
+    }
+
+    /// Syncs the client from its last synced block up to the chain tip
+    pub async fn sync(&mut self) -> Result<(), Box<dyn Error>> {
+        let time = Instant::now();
+        sync_state(
+            &self.faucet_ids,
+            &mut self.client,
+            self.sqlite_store.clone(),
+            self.rpc_api.clone(),
+        )
+        .await?;
+        println!("State synced in {:?}", time.elapsed());
+        Ok(())
+    }
 }